@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{error::AppError, is_safe_id, load_index, notes_dir};
+
+/// Attachments live in `notes/attachments/<note id>/`, next to the note files.
+pub(crate) const ATTACHMENTS_DIR: &str = "attachments";
+
+#[derive(Serialize)]
+pub struct Attachment {
+    name: String,
+    /// Path relative to the notes folder, suitable for a markdown link.
+    path: String,
+    size: u64,
+}
+
+/// Folder holding one note's attachments, relative to a notes directory.
+pub(crate) fn attachment_dir(notes_dir: &Path, note_id: &str) -> PathBuf {
+    notes_dir.join(ATTACHMENTS_DIR).join(note_id)
+}
+
+/// Files attached to a note as `(path on disk, path inside the notes folder)`.
+pub(crate) fn attachment_files(notes_dir: &Path, note_id: &str) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = fs::read_dir(attachment_dir(notes_dir, note_id)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_string();
+            Some((path, format!("{ATTACHMENTS_DIR}/{note_id}/{name}")))
+        })
+        .collect()
+}
+
+/// Removes every attachment of a deleted note. An id like `..` or `""` would
+/// name a folder above the note's own, so it removes nothing.
+pub(crate) fn remove_attachments(notes_dir: &Path, note_id: &str) {
+    if !is_safe_id(note_id) {
+        return;
+    }
+    let _ = fs::remove_dir_all(attachment_dir(notes_dir, note_id));
+}
+
+/// Only ids from the index are accepted, so an id can't name a folder outside `attachments/`.
+fn ensure_note(app: &AppHandle, note_id: &str) -> Result<(), AppError> {
+    if !load_index(app)?.iter().any(|meta| meta.id == note_id) {
+        return Err(AppError::NotFound(format!("Note {note_id} not found")));
+    }
+    Ok(())
+}
+
+/// Keeps only the final path component so a file name can't point outside the folder.
+fn clean_file_name(filename: &str) -> Result<String, AppError> {
+    Path::new(filename.trim())
+        .file_name()
+        .and_then(|s| s.to_str())
+        .filter(|name| !name.starts_with('.'))
+        .map(str::to_string)
+        .ok_or_else(|| AppError::InvalidInput(format!("Invalid attachment name: {filename}")))
+}
+
+/// `name.ext`, then `name-1.ext`, `name-2.ext`, ... until one is free.
+fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let ext = path.extension().and_then(|s| s.to_str());
+    (1..)
+        .map(|n| match ext {
+            Some(ext) => dir.join(format!("{stem}-{n}.{ext}")),
+            None => dir.join(format!("{stem}-{n}")),
+        })
+        .find(|p| !p.exists())
+        .unwrap_or(candidate)
+}
+
+/// Stores a file for a note and returns its path relative to the notes folder.
+/// An existing attachment with the same name is kept and the new one gets a suffix.
+#[tauri::command]
+pub fn save_attachment(app: AppHandle, note_id: String, filename: String, bytes: Vec<u8>) -> Result<String, AppError> {
+    ensure_note(&app, &note_id)?;
+    let dir = attachment_dir(&notes_dir(&app)?, &note_id);
+    let name = clean_file_name(&filename)?;
+    fs::create_dir_all(&dir)?;
+    let path = unused_path(&dir, &name);
+    fs::write(&path, bytes)?;
+    let stored = path.file_name().and_then(|s| s.to_str()).unwrap_or(&name);
+    Ok(format!("{ATTACHMENTS_DIR}/{note_id}/{stored}"))
+}
+
+#[tauri::command]
+pub fn list_attachments(app: AppHandle, note_id: String) -> Result<Vec<Attachment>, AppError> {
+    ensure_note(&app, &note_id)?;
+    let dir = notes_dir(&app)?;
+    let mut attachments: Vec<Attachment> = attachment_files(&dir, &note_id)
+        .into_iter()
+        .map(|(path, relative)| Attachment {
+            name: path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string(),
+            size: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            path: relative,
+        })
+        .collect();
+    attachments.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(attachments)
+}
+
+#[tauri::command]
+pub fn delete_attachment(app: AppHandle, note_id: String, filename: String) -> Result<(), AppError> {
+    ensure_note(&app, &note_id)?;
+    let path = attachment_dir(&notes_dir(&app)?, &note_id).join(clean_file_name(&filename)?);
+    fs::remove_file(path)?;
+    Ok(())
+}
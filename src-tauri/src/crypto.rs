@@ -0,0 +1,109 @@
+use std::{fs, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::error::AppError;
+
+const KDF_ROUNDS: u32 = 200_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Per-transfer key derivation and cipher parameters, sent in the transfer header.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct EncryptionParams {
+    salt: String,
+    nonce: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    key
+}
+
+fn decode(field: &str, value: &str, len: usize) -> Result<Vec<u8>, AppError> {
+    STANDARD
+        .decode(value)
+        .ok()
+        .filter(|bytes| bytes.len() == len)
+        .ok_or_else(|| AppError::Encryption(format!("Malformed encryption {field}")))
+}
+
+/// Writes `src` encrypted with a key derived from `passphrase` to `dest` and returns
+/// the parameters the receiver needs to reverse it. Every call uses a fresh salt and nonce.
+pub(crate) fn encrypt_file(src: &Path, dest: &Path, passphrase: &str) -> Result<EncryptionParams, AppError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let plaintext = fs::read(src)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| AppError::Encryption("Failed to encrypt transfer".into()))?;
+    fs::write(dest, ciphertext)?;
+
+    Ok(EncryptionParams { salt: STANDARD.encode(salt), nonce: STANDARD.encode(nonce) })
+}
+
+/// Decrypts a file written by [`encrypt_file`] in place. A wrong passphrase fails
+/// authentication, so tampered or mismatched data is never written out.
+pub(crate) fn decrypt_file(path: &Path, passphrase: &str, params: &EncryptionParams) -> Result<(), AppError> {
+    let salt = decode("salt", &params.salt, SALT_LEN)?;
+    let nonce = decode("nonce", &params.nonce, NONCE_LEN)?;
+
+    let key = derive_key(passphrase, &salt);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = fs::read(path)?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| AppError::Encryption("Could not decrypt transfer: passphrases don't match".into()))?;
+    fs::write(path, plaintext)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn decrypts_with_the_same_passphrase() {
+        let dir = TempDir::new();
+        let (plain, sealed) = (dir.path().join("notes.zip"), dir.path().join("notes.zip.enc"));
+        fs::write(&plain, b"archive bytes").unwrap();
+        let params = encrypt_file(&plain, &sealed, "correct horse").unwrap();
+        assert_ne!(fs::read(&sealed).unwrap(), b"archive bytes");
+        decrypt_file(&sealed, "correct horse", &params).unwrap();
+        assert_eq!(fs::read(&sealed).unwrap(), b"archive bytes");
+    }
+
+    #[test]
+    fn a_wrong_passphrase_fails_and_leaves_the_file_alone() {
+        let dir = TempDir::new();
+        let (plain, sealed) = (dir.path().join("notes.zip"), dir.path().join("notes.zip.enc"));
+        fs::write(&plain, b"archive bytes").unwrap();
+        let params = encrypt_file(&plain, &sealed, "correct horse").unwrap();
+        let ciphertext = fs::read(&sealed).unwrap();
+        assert!(matches!(decrypt_file(&sealed, "battery staple", &params), Err(AppError::Encryption(_))));
+        assert_eq!(fs::read(&sealed).unwrap(), ciphertext);
+    }
+
+    #[test]
+    fn malformed_params_are_rejected() {
+        let dir = TempDir::new();
+        let path = dir.path().join("notes.zip.enc");
+        fs::write(&path, b"x").unwrap();
+        let params = EncryptionParams { salt: STANDARD.encode([0u8; 4]), nonce: STANDARD.encode([0u8; NONCE_LEN]) };
+        assert!(decrypt_file(&path, "pass", &params).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+/// Error returned by every command. It reaches the frontend as
+/// `{ "kind": "notFound", "message": "..." }` so the UI can branch on `kind`.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Io(String),
+    Serde(String),
+    Network(String),
+    Conflict(String),
+    InvalidId(String),
+    InvalidInput(String),
+    Encryption(String),
+    /// The index lists the note but its `.md` file is gone.
+    MissingContent(String),
+}
+
+impl AppError {
+    pub(crate) fn network(err: impl fmt::Display) -> Self {
+        AppError::Network(err.to_string())
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "notFound",
+            AppError::Io(_) => "io",
+            AppError::Serde(_) => "serde",
+            AppError::Network(_) => "network",
+            AppError::Conflict(_) => "conflict",
+            AppError::InvalidId(_) => "invalidId",
+            AppError::InvalidInput(_) => "invalidInput",
+            AppError::Encryption(_) => "encryption",
+            AppError::MissingContent(_) => "missingContent",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            AppError::NotFound(m)
+            | AppError::Io(m)
+            | AppError::Serde(m)
+            | AppError::Network(m)
+            | AppError::Conflict(m)
+            | AppError::InvalidId(m)
+            | AppError::InvalidInput(m)
+            | AppError::Encryption(m)
+            | AppError::MissingContent(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", self.message())?;
+        state.end()
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotFound(err.to_string()),
+            _ => AppError::Io(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Serde(err.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(err: zip::result::ZipError) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<notify::Error> for AppError {
+    fn from(err: notify::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<std::net::AddrParseError> for AppError {
+    fn from(err: std::net::AddrParseError) -> Self {
+        AppError::InvalidInput(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_as_kind_and_message() {
+        let value = serde_json::to_value(AppError::MissingContent("gone".into())).unwrap();
+        assert_eq!(value, serde_json::json!({ "kind": "missingContent", "message": "gone" }));
+    }
+
+    #[test]
+    fn io_not_found_keeps_its_kind() {
+        let missing: AppError = std::io::Error::new(std::io::ErrorKind::NotFound, "no file").into();
+        assert!(matches!(missing, AppError::NotFound(_)));
+        let denied: AppError = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied").into();
+        assert!(matches!(denied, AppError::Io(m) if m == "denied"));
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fs, path::Path};
+
+use pulldown_cmark::{html, Parser};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{
+    error::AppError,
+    indexed_note_path, load_index,
+    markdown::{parser_options, plain_text},
+    note_file, note_path, notes_dir, parse_timestamp, StoredNoteMetadata,
+};
+
+#[derive(Serialize)]
+pub struct CombinedExport {
+    path: String,
+    notes: usize,
+    /// Notes that were skipped, with the reason.
+    warnings: Vec<String>,
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,-apple-system,sans-serif;max-width:46rem;margin:2rem auto;padding:0 1rem;line-height:1.6;color:#1f2328}\
+pre{background:#f6f8fa;padding:0.75rem;border-radius:6px;overflow-x:auto}\
+code{font-family:ui-monospace,monospace;font-size:0.9em}\
+blockquote{margin:0;padding-left:1rem;border-left:3px solid #d0d7de;color:#59636e}\
+table{border-collapse:collapse}th,td{border:1px solid #d0d7de;padding:0.25rem 0.5rem}\
+img{max-width:100%}";
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn markdown_to_html_document(title: &str, markdown: &str) -> String {
+    let mut body = String::new();
+    html::push_html(&mut body, Parser::new_ext(markdown, parser_options()));
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        HTML_STYLE,
+        body
+    )
+}
+
+#[tauri::command]
+pub fn export_note_html(app: AppHandle, id: String, out_path: String) -> Result<String, AppError> {
+    let meta = load_index(&app)?
+        .into_iter()
+        .find(|meta| meta.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("Note {id} not found")))?;
+    let content = fs::read_to_string(note_path(&app, &id)?)?;
+
+    let document = markdown_to_html_document(&meta.title, &content);
+    fs::write(&out_path, document)?;
+    Ok(out_path)
+}
+
+/// Writes every note into one markdown file, oldest first. Each note starts with an
+/// HTML comment carrying its id and dates so the file can be split back apart.
+#[tauri::command]
+pub fn export_combined_markdown(app: AppHandle, out_path: String) -> Result<CombinedExport, AppError> {
+    let (document, notes, warnings) = combined_markdown(&notes_dir(&app)?, load_index(&app)?);
+    fs::write(&out_path, document)?;
+    Ok(CombinedExport { path: out_path, notes, warnings })
+}
+
+/// The combined document for the notes of `index` in `dir`, how many notes it
+/// holds, and a warning for each note whose file couldn't be read.
+fn combined_markdown(dir: &Path, mut index: Vec<StoredNoteMetadata>) -> (String, usize, Vec<String>) {
+    index.sort_by_key(|meta| parse_timestamp(&meta.updated_at));
+
+    let mut document = String::new();
+    let mut warnings = Vec::new();
+    let mut notes = 0;
+    for meta in &index {
+        let content = match fs::read_to_string(note_file(dir, &meta.id)) {
+            Ok(content) => content,
+            Err(e) => {
+                warnings.push(format!("Skipped \"{}\": {}", meta.title, e));
+                continue;
+            }
+        };
+        if notes > 0 {
+            document.push_str("\n---\n\n");
+        }
+        document.push_str(&format!("<!-- note\nid: {}\n", meta.id));
+        if let Some(created) = &meta.created_at {
+            document.push_str(&format!("created: {created}\n"));
+        }
+        document.push_str(&format!("updated: {}\n-->\n# {}\n\n{}\n", meta.updated_at, meta.title, content.trim_end()));
+        notes += 1;
+    }
+    (document, notes, warnings)
+}
+
+/// The note body without markdown markup, for pasting into chat apps or emails.
+/// Paragraphs and list items keep their own lines.
+#[tauri::command]
+pub fn note_as_plaintext(app: AppHandle, id: String) -> Result<String, AppError> {
+    let content = fs::read_to_string(indexed_note_path(&app, &id)?)?;
+    Ok(plain_text(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_document_escapes_the_title_and_renders_the_body() {
+        let document = markdown_to_html_document("Tom & <Jerry> \"quoted\"", "# Heading\n\nSome *emphasis* and <b>html</b>.\n");
+        assert!(document.contains("<title>Tom &amp; &lt;Jerry&gt; &quot;quoted&quot;</title>"));
+        assert!(document.contains("<h1>Heading</h1>"));
+        assert!(document.contains("<p>Some <em>emphasis</em> and <b>html</b>.</p>"));
+        assert!(document.starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn combined_markdown_lists_notes_oldest_first_and_skips_missing_files() {
+        let dir = crate::testing::notes_folder(&[("new", "Newer", "second body\n"), ("old", "Older", "first body")]);
+        let mut index = crate::load_index_in(dir.path()).unwrap();
+        index[1].updated_at = "2023-06-01T00:00:00Z".into();
+        index[1].created_at = Some("2023-05-01T00:00:00Z".into());
+        index.push(crate::testing::meta("gone", "Missing"));
+
+        let (document, notes, warnings) = combined_markdown(dir.path(), index);
+        assert_eq!(notes, 2);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Missing"));
+        assert_eq!(
+            document,
+            "<!-- note\nid: old\ncreated: 2023-05-01T00:00:00Z\nupdated: 2023-06-01T00:00:00Z\n-->\n# Older\n\nfirst body\n\n---\n\n\
+             <!-- note\nid: new\nupdated: 2024-01-01T00:00:00Z\n-->\n# Newer\n\nsecond body\n"
+        );
+    }
+}
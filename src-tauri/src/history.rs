@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{error::AppError, now_timestamp};
+
+const HISTORY_FILE: &str = "transfer_history.json";
+/// Older entries are dropped once the log grows past this many records.
+const MAX_HISTORY_ENTRIES: usize = 500;
+const DEFAULT_LIST_LIMIT: usize = 50;
+
+/// Serializes read-modify-write cycles; sends and receives finish on different threads.
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRecord {
+    pub direction: TransferDirection,
+    /// Address of the other device.
+    pub peer: String,
+    /// Archive kind from the transfer header: "all", "single" or "selected".
+    pub kind: String,
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<u32>,
+    pub at: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TransferRecord {
+    pub(crate) fn new(direction: TransferDirection, peer: impl Into<String>, kind: impl Into<String>, bytes: u64) -> Self {
+        Self {
+            direction,
+            peer: peer.into(),
+            kind: kind.into(),
+            bytes,
+            notes: None,
+            at: now_timestamp(),
+            ok: true,
+            error: None,
+        }
+    }
+
+    pub(crate) fn notes(mut self, notes: Option<u32>) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    /// Marks the record as failed when `result` is an error.
+    pub(crate) fn outcome<T>(mut self, result: &Result<T, AppError>) -> Self {
+        if let Err(e) = result {
+            self.ok = false;
+            self.error = Some(e.to_string());
+        }
+        self
+    }
+}
+
+fn history_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let base = app.path().app_data_dir()?;
+    fs::create_dir_all(&base)?;
+    Ok(base.join(HISTORY_FILE))
+}
+
+fn load_history(app: &AppHandle) -> Result<Vec<TransferRecord>, AppError> {
+    load_history_from(&history_path(app)?)
+}
+
+fn load_history_from(path: &Path) -> Result<Vec<TransferRecord>, AppError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn append_record(app: &AppHandle, record: TransferRecord) -> Result<(), AppError> {
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    append_to(&history_path(app)?, record)
+}
+
+/// A log that can't be read is started over rather than blocking new records.
+fn append_to(path: &Path, record: TransferRecord) -> Result<(), AppError> {
+    let mut history = load_history_from(path).unwrap_or_default();
+    history.push(record);
+    if history.len() > MAX_HISTORY_ENTRIES {
+        history.drain(..history.len() - MAX_HISTORY_ENTRIES);
+    }
+    let data = serde_json::to_string_pretty(&history)?;
+    fs::write(path, data).map_err(AppError::from)
+}
+
+/// Appends to the transfer log. Failing to write history must never fail the transfer itself.
+pub(crate) fn record_transfer(app: &AppHandle, record: TransferRecord) {
+    let _ = append_record(app, record);
+}
+
+/// Most recent transfers first.
+#[tauri::command]
+pub fn list_transfers(app: AppHandle, limit: Option<usize>) -> Result<Vec<TransferRecord>, AppError> {
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let history = load_history(&app)?;
+    Ok(history.into_iter().rev().take(limit.unwrap_or(DEFAULT_LIST_LIMIT)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn outcome_records_the_error() {
+        let ok = TransferRecord::new(TransferDirection::Sent, "10.0.0.2", "all", 10).outcome(&Ok::<(), AppError>(()));
+        assert!(ok.ok && ok.error.is_none());
+        let failed = TransferRecord::new(TransferDirection::Sent, "10.0.0.2", "all", 10).outcome(&Err::<(), _>(AppError::Network("refused".into())));
+        assert!(!failed.ok);
+        assert_eq!(failed.error.as_deref(), Some("refused"));
+    }
+
+    #[test]
+    fn append_keeps_only_the_newest_records() {
+        let dir = TempDir::new();
+        let path = dir.path().join(HISTORY_FILE);
+        for bytes in 0..MAX_HISTORY_ENTRIES as u64 + 3 {
+            append_to(&path, TransferRecord::new(TransferDirection::Received, "10.0.0.2", "all", bytes)).unwrap();
+        }
+        let history = load_history_from(&path).unwrap();
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!((history[0].bytes, history.last().unwrap().bytes), (3, MAX_HISTORY_ENTRIES as u64 + 2));
+    }
+
+    #[test]
+    fn append_starts_over_after_a_damaged_log() {
+        let dir = TempDir::new();
+        let path = dir.path().join(HISTORY_FILE);
+        fs::write(&path, "not json").unwrap();
+        append_to(&path, TransferRecord::new(TransferDirection::Sent, "10.0.0.2", "single", 1).notes(Some(1))).unwrap();
+        let history = load_history_from(&path).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].notes, Some(1));
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashSet, fs, path::Path, time::SystemTime};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    build_summary_in, emit_note_changed, error::AppError, load_index_in, lock_index, note_file, notes_dir, save_index_in,
+    watcher, NoteSummary, StoredNoteMetadata,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkdownImport {
+    imported: Vec<NoteSummary>,
+    /// Files whose content already exists as a note.
+    skipped: Vec<String>,
+    /// Files that aren't valid UTF-8 text.
+    unreadable: Vec<String>,
+}
+
+fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_markdown(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()).map(str::to_ascii_lowercase).as_deref(),
+        Some("md" | "markdown")
+    )
+}
+
+/// The first heading if the file has one, otherwise the file name.
+fn import_title(path: &Path, content: &str) -> String {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with('#'))
+        .map(|line| line.trim_start_matches('#').trim().to_string())
+        .filter(|heading| !heading.is_empty())
+        .or_else(|| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time).format(&Rfc3339).unwrap_or_default()
+}
+
+/// Creates a note for every markdown file directly inside `dir_path`. Files whose
+/// content matches an existing note are skipped, so importing twice is harmless.
+#[tauri::command]
+pub fn import_markdown_dir(app: AppHandle, dir_path: String) -> Result<MarkdownImport, AppError> {
+    let _guard = lock_index(&app)?;
+    let result = import_markdown_in(&notes_dir(&app)?, Path::new(&dir_path))?;
+    for summary in &result.imported {
+        emit_note_changed(&app, summary);
+    }
+    Ok(result)
+}
+
+fn import_markdown_in(notes_dir: &Path, dir: &Path) -> Result<MarkdownImport, AppError> {
+    if !dir.is_dir() {
+        return Err(AppError::NotFound(format!("{} is not a folder", dir.display())));
+    }
+
+    let mut index = load_index_in(notes_dir)?;
+    let mut known: HashSet<String> = index
+        .iter()
+        .filter_map(|meta| fs::read_to_string(note_file(notes_dir, &meta.id)).ok())
+        .map(|content| content_hash(&content))
+        .collect();
+
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_markdown(path))
+        .collect();
+    files.sort();
+
+    let mut imported = Vec::new();
+    let mut skipped = Vec::new();
+    let mut unreadable = Vec::new();
+    for path in files {
+        let content = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                unreadable.push(path.to_string_lossy().into_owned());
+                continue;
+            }
+            result => result?,
+        };
+        if !known.insert(content_hash(&content)) {
+            skipped.push(path.to_string_lossy().into_owned());
+            continue;
+        }
+
+        let file_meta = fs::metadata(&path)?;
+        let modified = file_meta.modified().map(format_time).unwrap_or_else(|_| crate::now_timestamp());
+        let created = file_meta.created().map(format_time).unwrap_or_else(|_| modified.clone());
+        let meta = StoredNoteMetadata {
+            id: Uuid::new_v4().to_string(),
+            title: import_title(&path, &content),
+            updated_at: modified,
+            created_at: Some(created),
+            color: None,
+            tags: Vec::new(),
+            archived: false,
+            extension: None,
+        };
+        watcher::mark_own_write(&meta.id);
+        fs::write(note_file(notes_dir, &meta.id), &content)?;
+        index.push(meta.clone());
+        imported.push(meta);
+    }
+
+    if !imported.is_empty() {
+        save_index_in(notes_dir, &index)?;
+    }
+    let imported: Vec<NoteSummary> = imported.into_iter().map(|meta| build_summary_in(notes_dir, meta)).collect();
+    Ok(MarkdownImport { imported, skipped, unreadable })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{notes_folder, TempDir};
+
+    fn source(files: &[(&str, &[u8])]) -> TempDir {
+        let dir = TempDir::new();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn imports_each_markdown_file_as_a_note() {
+        let notes = notes_folder(&[]);
+        let src = source(&[("first.md", b"# Heading\nbody"), ("second.markdown", b"no heading"), ("skip.txt", b"text")]);
+
+        let result = import_markdown_in(notes.path(), src.path()).unwrap();
+        assert_eq!(result.imported.len(), 2);
+        let index = load_index_in(notes.path()).unwrap();
+        let titles: Vec<&str> = index.iter().map(|meta| meta.title.as_str()).collect();
+        assert_eq!(titles, ["Heading", "second"]);
+        assert_eq!(fs::read_to_string(note_file(notes.path(), &index[1].id)).unwrap(), "no heading");
+        assert!(index.iter().all(|meta| meta.created_at.is_some()));
+    }
+
+    #[test]
+    fn reimporting_skips_files_already_imported() {
+        let notes = notes_folder(&[("a", "A", "existing")]);
+        let src = source(&[("one.md", b"one"), ("dup.md", b"existing"), ("bad.md", &[0xff, 0xfe])]);
+
+        let first = import_markdown_in(notes.path(), src.path()).unwrap();
+        assert_eq!(first.imported.len(), 1);
+        assert_eq!(first.skipped.len(), 1);
+        assert_eq!(first.unreadable.len(), 1);
+
+        let second = import_markdown_in(notes.path(), src.path()).unwrap();
+        assert!(second.imported.is_empty());
+        assert_eq!(second.skipped.len(), 2);
+        assert_eq!(load_index_in(notes.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn import_title_prefers_the_first_heading() {
+        assert_eq!(import_title(Path::new("x/file.md"), "text\n## Sub\n# Main"), "Sub");
+        assert_eq!(import_title(Path::new("x/file.md"), "#\nbody"), "file");
+    }
+}
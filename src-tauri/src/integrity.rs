@@ -0,0 +1,163 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use serde::Serialize;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::{error::AppError, is_note_file, load_index_in, note_file, notes_dir, parse_timestamp, INDEX_FILE};
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ProblemKind {
+    /// Listed in the index but has no note file. `compact_index` drops these.
+    MissingFile,
+    /// A note file the index doesn't know about.
+    UnindexedFile,
+    /// Not a UUID, the only kind of id new notes get.
+    InvalidId,
+    /// Listed more than once. `compact_index` keeps the newest entry.
+    DuplicateId,
+    InvalidTimestamp,
+}
+
+#[derive(Serialize)]
+pub struct Problem {
+    kind: ProblemKind,
+    id: String,
+    detail: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    /// Why `index.json` couldn't be read; every note file is then unindexed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_error: Option<String>,
+    problems: Vec<Problem>,
+}
+
+/// Audits the notes folder without changing anything, so the UI can offer the
+/// matching repair.
+#[tauri::command]
+pub fn check_integrity(app: AppHandle) -> Result<IntegrityReport, AppError> {
+    check_integrity_in(&notes_dir(&app)?)
+}
+
+fn check_integrity_in(dir: &Path) -> Result<IntegrityReport, AppError> {
+    let mut problems = Vec::new();
+    let (index, index_error) = match load_index_in(dir) {
+        Ok(index) => (index, None),
+        Err(err) => (Vec::new(), Some(format!("{}: {}", dir.join(INDEX_FILE).display(), err))),
+    };
+
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for meta in &index {
+        if !seen.insert(meta.id.as_str()) {
+            if reported.insert(meta.id.as_str()) {
+                problems.push(Problem {
+                    kind: ProblemKind::DuplicateId,
+                    id: meta.id.clone(),
+                    detail: format!("\"{}\" is listed more than once", meta.title),
+                });
+            }
+            continue;
+        }
+        if Uuid::parse_str(&meta.id).is_err() {
+            problems.push(Problem {
+                kind: ProblemKind::InvalidId,
+                id: meta.id.clone(),
+                detail: format!("\"{}\" has an id that isn't a UUID", meta.title),
+            });
+        }
+        if !note_file(dir, &meta.id).exists() {
+            problems.push(Problem {
+                kind: ProblemKind::MissingFile,
+                id: meta.id.clone(),
+                detail: format!("\"{}\" has no note file", meta.title),
+            });
+        }
+        let timestamps = [Some(("updatedAt", &meta.updated_at)), meta.created_at.as_ref().map(|t| ("createdAt", t))];
+        for (field, value) in timestamps.into_iter().flatten() {
+            if parse_timestamp(value).is_none() {
+                problems.push(Problem {
+                    kind: ProblemKind::InvalidTimestamp,
+                    id: meta.id.clone(),
+                    detail: format!("{field} \"{value}\" isn't a valid timestamp"),
+                });
+            }
+        }
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !is_note_file(&path) {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+        if !seen.contains(id) {
+            problems.push(Problem {
+                kind: ProblemKind::UnindexedFile,
+                id: id.to_string(),
+                detail: format!("{} isn't in the index", path.display()),
+            });
+        }
+    }
+
+    Ok(IntegrityReport { index_error, problems })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{save_index_in, testing};
+
+    fn found(report: &IntegrityReport) -> Vec<(&ProblemKind, &str)> {
+        report.problems.iter().map(|problem| (&problem.kind, problem.id.as_str())).collect()
+    }
+
+    #[test]
+    fn a_healthy_folder_has_no_problems() {
+        let id = Uuid::new_v4().to_string();
+        let dir = testing::notes_folder(&[(&id, "Fine", "body")]);
+        let report = check_integrity_in(dir.path()).unwrap();
+        assert!(report.index_error.is_none());
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn each_kind_of_problem_is_reported_with_its_id() {
+        let (good, missing, dated) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        let dir = testing::notes_folder(&[(&good, "Good", "a"), ("legacy", "Legacy", "b"), (&dated, "Dated", "c")]);
+        let mut index = load_index_in(dir.path()).unwrap();
+        index.push(testing::meta(&good, "Good again"));
+        index.push(testing::meta(&missing, "Gone"));
+        index[2].updated_at = "yesterday".into();
+        save_index_in(dir.path(), &index).unwrap();
+        fs::write(dir.path().join("stray.md"), "unlisted").unwrap();
+
+        let report = check_integrity_in(dir.path()).unwrap();
+        let mut problems = found(&report);
+        problems.sort_by_key(|(_, id)| *id);
+        let mut expected = vec![
+            (&ProblemKind::DuplicateId, good.as_str()),
+            (&ProblemKind::InvalidId, "legacy"),
+            (&ProblemKind::InvalidTimestamp, dated.as_str()),
+            (&ProblemKind::MissingFile, missing.as_str()),
+            (&ProblemKind::UnindexedFile, "stray"),
+        ];
+        expected.sort_by_key(|(_, id)| *id);
+        assert_eq!(problems, expected);
+    }
+
+    #[test]
+    fn an_unreadable_index_leaves_every_file_unindexed() {
+        let dir = testing::notes_folder(&[("a", "A", "body")]);
+        fs::write(dir.path().join(INDEX_FILE), "{ not json").unwrap();
+
+        let report = check_integrity_in(dir.path()).unwrap();
+        assert!(report.index_error.is_some());
+        assert_eq!(found(&report), [(&ProblemKind::UnindexedFile, "a")]);
+    }
+}
@@ -1,15 +1,47 @@
 use std::{
     fs,
-    path::PathBuf,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
 };
 
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Emitter, Manager};
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+use error::AppError;
+mod attachments;
+mod crypto;
+mod error;
+mod export;
+mod history;
+mod import;
+mod integrity;
+mod links;
+mod markdown;
+mod notes_lock;
+mod pairing;
+mod search;
+mod settings;
 mod share;
+mod stats;
+mod tags;
+mod templates;
+#[cfg(test)]
+mod testing;
+mod toc;
+mod versions;
+mod watcher;
 
 const NOTES_DIR: &str = "notes";
 const INDEX_FILE: &str = "index.json";
 const PREVIEW_MAX_CHARS: usize = 200;
+const DERIVED_TITLE_MAX_CHARS: usize = 80;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +49,18 @@ pub(crate) struct StoredNoteMetadata {
     id: String,
     title: String,
     updated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Hidden from `list_notes` but still searchable and shareable.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+    /// File extension of the note, without the dot. Absent means `md`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -26,6 +70,12 @@ struct NoteSummary {
     title: String,
     updated_at: String,
     preview: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -35,180 +85,1664 @@ struct NoteDocument {
     title: String,
     content: String,
     updated_at: String,
+    /// The rest of the note's metadata, filled in by `load_note`. `save_note`
+    /// ignores these; they have their own commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+}
+
+pub(crate) fn now_timestamp() -> String {
+    time::OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
 }
 
-pub(crate) fn notes_dir(app: &AppHandle) -> Result<PathBuf, String> {
-    let base = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
-    let dir = base.join(NOTES_DIR);
-    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+/// Parses a stored timestamp. RFC3339 is canonical, but older writers used
+/// `YYYY-MM-DD HH:MM:SS` in UTC, so that is accepted too.
+pub(crate) fn parse_timestamp(value: &str) -> Option<OffsetDateTime> {
+    let value = value.trim();
+    OffsetDateTime::parse(value, &Rfc3339).ok().or_else(|| {
+        PrimitiveDateTime::parse(value, format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"))
+            .ok()
+            .map(PrimitiveDateTime::assume_utc)
+    })
+}
+
+/// Rewrites any accepted timestamp as RFC3339 in UTC.
+fn normalize_timestamp(value: &str) -> Option<String> {
+    parse_timestamp(value)?.to_offset(UtcOffset::UTC).format(&Rfc3339).ok()
+}
+
+fn default_notes_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    Ok(app.path().app_data_dir()?.join(NOTES_DIR))
+}
+
+pub(crate) fn notes_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let dir = chosen_notes_dir(settings::load_settings(app).notes_directory, default_notes_dir(app)?);
+    fs::create_dir_all(&dir)?;
     Ok(dir)
 }
 
-fn index_path(app: &AppHandle) -> Result<PathBuf, String> {
+/// The folder set in the settings, or `default` when none is.
+fn chosen_notes_dir(custom: Option<String>, default: PathBuf) -> PathBuf {
+    custom.map(PathBuf::from).unwrap_or(default)
+}
+
+/// Fails unless a file can actually be created in `dir`.
+fn check_writable(dir: &Path) -> Result<(), AppError> {
+    let probe = dir.join(format!(".write-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&probe, b""))
+        .map_err(|e| AppError::InvalidInput(format!("{} is not writable: {}", dir.display(), e)))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+/// Moves a file or folder, copying when a plain rename can't cross devices.
+fn move_entry(from: &Path, to: &Path) -> Result<(), AppError> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)?;
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Moves the notes data from `current` into `target`, leaving anything else
+/// in `current` behind. Refuses when `target` already has an index.
+fn migrate_notes(current: &Path, target: &Path) -> Result<(), AppError> {
+    if target.join(INDEX_FILE).exists() {
+        return Err(AppError::Conflict(format!("{} already contains notes", target.display())));
+    }
+    for entry in fs::read_dir(current)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let is_notes_data = name == INDEX_FILE
+            || is_note_file(&path)
+            || name == attachments::ATTACHMENTS_DIR
+            || name == templates::TEMPLATES_DIR
+            || name == versions::HISTORY_DIR
+            || name == share::BACKUPS_DIR;
+        if is_notes_data {
+            move_entry(&path, &target.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Points the app at a different notes folder. With `migrate`, the index, note
+/// files and their attachment, template and history folders are moved there first.
+/// An empty path goes back to the default location.
+#[tauri::command]
+fn set_notes_directory(app: AppHandle, path: String, migrate: Option<bool>) -> Result<String, AppError> {
+    let _guard = lock_index(&app)?;
+    let target = match path.trim() {
+        "" => default_notes_dir(&app)?,
+        custom => PathBuf::from(custom),
+    };
+    if !target.is_absolute() {
+        return Err(AppError::InvalidInput("Notes directory must be an absolute path".into()));
+    }
+    check_writable(&target)?;
+
+    let current = notes_dir(&app)?;
+    if migrate.unwrap_or(false) && current != target {
+        migrate_notes(&current, &target)?;
+    }
+
+    let mut settings = settings::load_settings(&app);
+    settings.notes_directory = (target != default_notes_dir(&app)?).then(|| target.to_string_lossy().into_owned());
+    settings::save_settings(&app, &settings)?;
+    watcher::rewatch(&app)?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+fn index_path(app: &AppHandle) -> Result<PathBuf, AppError> {
     Ok(notes_dir(app)?.join(INDEX_FILE))
 }
 
-fn note_path(app: &AppHandle, note_id: &str) -> Result<PathBuf, String> {
-    Ok(notes_dir(app)?.join(format!("{note_id}.md")))
+/// Extensions a note file may have. The first one is the default.
+pub(crate) const NOTE_EXTENSIONS: &[&str] = &["md", "txt", "markdown"];
+
+pub(crate) fn is_note_file(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().and_then(|s| s.to_str()).is_some_and(|ext| NOTE_EXTENSIONS.contains(&ext))
 }
 
-fn load_index(app: &AppHandle) -> Result<Vec<StoredNoteMetadata>, String> {
-    let path = index_path(app)?;
+/// Accepts `txt`, `.TXT` and the like; the default extension is stored as `None`.
+fn validate_extension(extension: Option<String>) -> Result<Option<String>, AppError> {
+    let Some(raw) = extension else { return Ok(None) };
+    let ext = raw.trim().trim_start_matches('.').to_lowercase();
+    if ext.is_empty() || ext == NOTE_EXTENSIONS[0] {
+        return Ok(None);
+    }
+    if !NOTE_EXTENSIONS.contains(&ext.as_str()) {
+        return Err(AppError::InvalidInput(format!("Unsupported note format: {raw}")));
+    }
+    Ok(Some(ext))
+}
+
+impl StoredNoteMetadata {
+    pub(crate) fn file_name(&self) -> String {
+        format!("{}.{}", self.id, self.extension.as_deref().unwrap_or(NOTE_EXTENSIONS[0]))
+    }
+}
+
+/// The file holding a note in `dir`, whichever supported extension it has.
+/// A note without a file yet gets the default extension.
+pub(crate) fn note_file(dir: &Path, note_id: &str) -> PathBuf {
+    NOTE_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{note_id}.{ext}")))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| dir.join(format!("{note_id}.{}", NOTE_EXTENSIONS[0])))
+}
+
+fn note_path(app: &AppHandle, note_id: &str) -> Result<PathBuf, AppError> {
+    Ok(note_file(&notes_dir(app)?, note_id))
+}
+
+/// Whether `id` can name a file or folder inside the notes folder without
+/// reaching outside it or matching a whole folder, like `..` or the empty id.
+pub(crate) fn is_safe_id(id: &str) -> bool {
+    !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\\']) && !id.contains("..")
+}
+
+/// `note_path` for an id that came from the frontend. Only ids in the index
+/// resolve, so something like `../x` can't reach files outside the notes folder.
+pub(crate) fn indexed_note_path(app: &AppHandle, note_id: &str) -> Result<PathBuf, AppError> {
+    if !load_index(app)?.iter().any(|meta| meta.id == note_id) {
+        return Err(AppError::NotFound("Note metadata missing".into()));
+    }
+    note_path(app, note_id)
+}
+
+/// Held across every load/modify/save of `index.json` so concurrent commands
+/// can't overwrite each other's changes. Plain reads don't need it.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Also claims the notes folder against other instances; see `notes_lock`.
+pub(crate) fn lock_index(app: &AppHandle) -> Result<MutexGuard<'static, ()>, AppError> {
+    lock_notes_dir(&notes_dir(app)?)
+}
+
+/// `lock_index` for a known notes folder. A panic mid-update leaves the file as
+/// it was, so a poisoned lock is still usable.
+pub(crate) fn lock_notes_dir(dir: &Path) -> Result<MutexGuard<'static, ()>, AppError> {
+    let guard = INDEX_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    notes_lock::hold(dir)?;
+    Ok(guard)
+}
+
+/// Version written by `save_index`. Bump it, and add a step to `migrate_index`,
+/// whenever existing entries need backfilling for a new field.
+const INDEX_VERSION: u32 = 2;
+
+/// `index.json` as stored: version 1 was a bare array of entries.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IndexFile {
+    Versioned { version: u32, notes: Vec<StoredNoteMetadata> },
+    Legacy(Vec<StoredNoteMetadata>),
+}
+
+#[derive(Serialize)]
+struct IndexEnvelope<'a> {
+    version: u32,
+    notes: &'a [StoredNoteMetadata],
+}
+
+/// Brings entries read from an index of version `from` up to `INDEX_VERSION`.
+fn migrate_index(notes: &mut [StoredNoteMetadata], from: u32) {
+    if from < 2 {
+        // Version 1 predates `created_at`; the last edit is the best guess we have.
+        for meta in notes.iter_mut().filter(|meta| meta.created_at.is_none()) {
+            meta.created_at = Some(meta.updated_at.clone());
+        }
+    }
+}
+
+/// Parses either index layout, migrated to the current version. Also returns the
+/// version the data was stored with.
+pub(crate) fn parse_index(data: &str) -> Result<(Vec<StoredNoteMetadata>, u32), AppError> {
+    let (mut notes, version) = match serde_json::from_str(data)? {
+        IndexFile::Versioned { version, notes } => (notes, version),
+        IndexFile::Legacy(notes) => (notes, 1),
+    };
+    migrate_index(&mut notes, version);
+    Ok((notes, version))
+}
+
+pub(crate) fn index_json(notes: &[StoredNoteMetadata]) -> Result<String, AppError> {
+    Ok(serde_json::to_string_pretty(&IndexEnvelope { version: INDEX_VERSION, notes })?)
+}
+
+fn load_index(app: &AppHandle) -> Result<Vec<StoredNoteMetadata>, AppError> {
+    load_index_in(&notes_dir(app)?)
+}
+
+/// `load_index` for a known notes folder.
+fn load_index_in(dir: &Path) -> Result<Vec<StoredNoteMetadata>, AppError> {
+    let path = dir.join(INDEX_FILE);
     if !path.exists() {
         return Ok(Vec::new());
     }
 
-    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let notes: Vec<StoredNoteMetadata> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    Ok(notes)
+    let data = fs::read_to_string(path)?;
+    Ok(parse_index(&data)?.0)
 }
 
-fn save_index(app: &AppHandle, notes: &[StoredNoteMetadata]) -> Result<(), String> {
+/// Rewrites an index stored by an older version so the file on disk records the
+/// migrations that ran. Called once at startup; `load_index` migrates in memory regardless.
+fn upgrade_index(app: &AppHandle) -> Result<(), AppError> {
+    let _guard = lock_index(app)?;
     let path = index_path(app)?;
-    let data = serde_json::to_string_pretty(notes).map_err(|e| e.to_string())?;
-    fs::write(path, data).map_err(|e| e.to_string())
+    if !path.exists() {
+        return Ok(());
+    }
+    let (notes, version) = parse_index(&fs::read_to_string(&path)?)?;
+    if version < INDEX_VERSION {
+        save_index(app, &notes)?;
+    }
+    Ok(())
 }
 
-pub(crate) fn preview_from_content(content: &str) -> String {
-    // Preserve line breaks so markdown blocks (headings, lists, quotes)
-    // still render correctly in the home card preview.
-    let mut preview = String::new();
-    for line in content.lines() {
+fn save_index(app: &AppHandle, notes: &[StoredNoteMetadata]) -> Result<(), AppError> {
+    save_index_in(&notes_dir(app)?, notes)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Index writes made by the current test thread.
+    static INDEX_WRITES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn save_index_in(dir: &Path, notes: &[StoredNoteMetadata]) -> Result<(), AppError> {
+    #[cfg(test)]
+    INDEX_WRITES.with(|writes| writes.set(writes.get() + 1));
+    fs::write(dir.join(INDEX_FILE), index_json(notes)?)?;
+    tags::invalidate_tag_counts();
+    Ok(())
+}
+
+/// `Settings::preview_length` and `Settings::strip_markdown_previews`, kept here so
+/// building a summary doesn't read the settings file. See `apply_preview_settings`.
+static PREVIEW_LENGTH: AtomicUsize = AtomicUsize::new(PREVIEW_MAX_CHARS);
+static STRIP_MARKDOWN_PREVIEWS: AtomicBool = AtomicBool::new(false);
+
+/// Makes later previews follow `settings`. Called on startup and whenever settings are saved.
+pub(crate) fn apply_preview_settings(settings: &settings::Settings) {
+    PREVIEW_LENGTH.store(settings.preview_length, Ordering::Relaxed);
+    STRIP_MARKDOWN_PREVIEWS.store(settings.strip_markdown_previews, Ordering::Relaxed);
+}
+
+/// Builds a preview line by line, so a file can be read only as far as needed.
+struct PreviewBuilder {
+    preview: String,
+    max_len: usize,
+    strip_markdown: bool,
+}
+
+impl PreviewBuilder {
+    fn new(max_len: usize, strip_markdown: bool) -> Self {
+        Self { preview: String::new(), max_len, strip_markdown }
+    }
+
+    /// A builder following the preview settings.
+    fn configured() -> Self {
+        Self::new(PREVIEW_LENGTH.load(Ordering::Relaxed), STRIP_MARKDOWN_PREVIEWS.load(Ordering::Relaxed))
+    }
+
+    /// Adds one line; returns false once the preview is full.
+    fn push_line(&mut self, line: &str) -> bool {
+        // Preserve line breaks so markdown blocks (headings, lists, quotes)
+        // still render correctly in the home card preview.
         let trimmed = line.trim_end();
         // Skip leading empty lines but keep subsequent empties to delimit blocks
-        if preview.is_empty() && trimmed.trim().is_empty() {
-            continue;
+        if self.preview.is_empty() && trimmed.trim().is_empty() {
+            return true;
         }
 
-        if !preview.is_empty() {
-            preview.push('\n');
+        if !self.preview.is_empty() {
+            self.preview.push('\n');
         }
 
-        preview.push_str(trimmed);
+        self.preview.push_str(trimmed);
+
+        self.preview.len() <= self.max_len
+    }
 
-        if preview.len() > PREVIEW_MAX_CHARS {
+    fn finish(mut self) -> String {
+        if self.strip_markdown {
+            self.preview = markdown::plain_text(&self.preview);
+        }
+        if self.preview.len() > self.max_len {
+            let mut truncate_at = self.max_len.saturating_sub(3);
+            // Don't cut a multi-byte character in half.
+            while !self.preview.is_char_boundary(truncate_at) {
+                truncate_at -= 1;
+            }
+            self.preview.truncate(truncate_at);
+            self.preview.push_str("...");
+        }
+        self.preview
+    }
+}
+
+pub(crate) fn preview_from_content(content: &str) -> String {
+    let mut builder = PreviewBuilder::configured();
+    for line in content.lines() {
+        if !builder.push_line(line) {
             break;
         }
     }
+    builder.finish()
+}
 
-    if preview.len() > PREVIEW_MAX_CHARS {
-        let truncate_at = PREVIEW_MAX_CHARS.saturating_sub(3);
-        preview.truncate(truncate_at);
-        preview.push_str("...");
+/// `preview_from_content` for a note file, reading only the lines the preview
+/// needs. Invalid UTF-8 is replaced as in `read_note_lossy`.
+fn preview_from_file(path: &Path) -> std::io::Result<String> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut builder = PreviewBuilder::configured();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        // The line ending is left on; `push_line` trims it like any trailing whitespace.
+        if !builder.push_line(&String::from_utf8_lossy(&line)) {
+            break;
+        }
     }
+    Ok(builder.finish())
+}
+
+/// Note content with invalid UTF-8 replaced, for previews and search where a
+/// damaged file shouldn't make the note disappear.
+pub(crate) fn read_note_lossy(path: &Path) -> std::io::Result<String> {
+    fs::read(path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Title used when a note is saved without one: the first non-empty line,
+/// without heading markers, or "Untitled" for an empty note.
+fn derive_title(content: &str) -> String {
+    let first_line = content
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .find(|line| !line.is_empty());
+
+    match first_line {
+        Some(line) if line.chars().count() > DERIVED_TITLE_MAX_CHARS => {
+            let truncated: String = line.chars().take(DERIVED_TITLE_MAX_CHARS).collect();
+            format!("{}...", truncated.trim_end())
+        }
+        Some(line) => line.to_string(),
+        None => "Untitled".to_string(),
+    }
+}
+
+fn note_changed_payload(summary: &NoteSummary) -> serde_json::Value {
+    serde_json::json!({ "id": summary.id, "summary": summary })
+}
+
+fn note_deleted_payload(id: &str) -> serde_json::Value {
+    serde_json::json!({ "id": id })
+}
 
-    preview
+/// Lets other windows refresh their note list after a successful write.
+fn emit_note_changed(app: &AppHandle, summary: &NoteSummary) {
+    let _ = app.emit("note://changed", note_changed_payload(summary));
 }
 
 fn build_summary(app: &AppHandle, meta: StoredNoteMetadata) -> NoteSummary {
     let preview = note_path(app, &meta.id)
         .ok()
-        .and_then(|path| fs::read_to_string(path).ok())
-        .map(|content| preview_from_content(&content))
+        .and_then(|path| preview_from_file(&path).ok())
         .unwrap_or_default();
+    summary_with_preview(meta, preview)
+}
+
+/// `build_summary` for a known notes folder.
+fn build_summary_in(dir: &Path, meta: StoredNoteMetadata) -> NoteSummary {
+    let preview = preview_from_file(&note_file(dir, &meta.id)).unwrap_or_default();
+    summary_with_preview(meta, preview)
+}
 
+fn summary_with_preview(meta: StoredNoteMetadata, preview: String) -> NoteSummary {
     NoteSummary {
         preview,
         id: meta.id,
         title: meta.title,
         updated_at: meta.updated_at,
+        color: meta.color,
+        tags: meta.tags,
+        archived: meta.archived,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NotesPage {
+    notes: Vec<NoteSummary>,
+    /// Number of notes in the whole library, not just this page.
+    total: usize,
+}
+
+/// Newest first; unparseable timestamps go last.
+fn sort_newest_first(index: &mut [StoredNoteMetadata]) {
+    index.sort_by_key(|meta| std::cmp::Reverse(parse_timestamp(&meta.updated_at)));
+}
+
+/// Order of the note list.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum NoteSort {
+    /// Most recently updated first.
+    #[default]
+    Updated,
+    /// Most recently created first.
+    Created,
+    /// Alphabetical by title.
+    Title,
+}
+
+/// Sort key that ignores case and accents, so `apple`, `Äpfel` and `Zebra`
+/// come out in the order a person would expect.
+fn title_sort_key(title: &str) -> String {
+    title
+        .trim()
+        .nfd()
+        .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn sort_notes(index: &mut [StoredNoteMetadata], sort: NoteSort) {
+    match sort {
+        NoteSort::Updated => sort_newest_first(index),
+        NoteSort::Created => index.sort_by_key(|meta| {
+            std::cmp::Reverse(parse_timestamp(meta.created_at.as_deref().unwrap_or(&meta.updated_at)))
+        }),
+        // Ties on the folded key fall back to the raw title so the order is stable.
+        NoteSort::Title => index.sort_by_cached_key(|meta| (title_sort_key(&meta.title), meta.title.clone())),
     }
 }
 
+/// Lists unarchived notes, newest first unless `sort` says otherwise. `offset`
+/// and `limit` select a page of that order; without them every note is returned.
 #[tauri::command]
-fn list_notes(app: AppHandle) -> Result<Vec<NoteSummary>, String> {
-    let index = load_index(&app)?;
-    let summaries = index.into_iter().map(|meta| build_summary(&app, meta)).collect();
-    Ok(summaries)
+fn list_notes(
+    app: AppHandle,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<NoteSort>,
+) -> Result<NotesPage, AppError> {
+    list_notes_in(&notes_dir(&app)?, offset, limit, sort)
+}
+
+fn list_notes_in(
+    dir: &Path,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<NoteSort>,
+) -> Result<NotesPage, AppError> {
+    let mut index: Vec<StoredNoteMetadata> = load_index_in(dir)?.into_iter().filter(|meta| !meta.archived).collect();
+    // Sorting before slicing keeps pages stable.
+    sort_notes(&mut index, sort.unwrap_or_default());
+    let total = index.len();
+    let notes = index
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|meta| build_summary_in(dir, meta))
+        .collect();
+    Ok(NotesPage { notes, total })
 }
 
 #[tauri::command]
-fn load_note(app: AppHandle, id: String) -> Result<NoteDocument, String> {
-    let path = note_path(&app, &id)?;
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let mut index = load_index(&app)?.into_iter();
+fn list_archived(app: AppHandle) -> Result<Vec<NoteSummary>, AppError> {
+    list_archived_in(&notes_dir(&app)?)
+}
+
+fn list_archived_in(dir: &Path) -> Result<Vec<NoteSummary>, AppError> {
+    let mut index: Vec<StoredNoteMetadata> = load_index_in(dir)?.into_iter().filter(|meta| meta.archived).collect();
+    sort_newest_first(&mut index);
+    Ok(index.into_iter().map(|meta| build_summary_in(dir, meta)).collect())
+}
+
+/// Applies `change` to one note's index entry and broadcasts the new summary.
+/// For metadata-only edits: the note file is never rewritten.
+pub(crate) fn update_metadata(
+    app: &AppHandle,
+    id: &str,
+    change: impl FnOnce(&mut StoredNoteMetadata),
+) -> Result<NoteSummary, AppError> {
+    let _guard = lock_index(app)?;
+    let summary = update_metadata_in(&notes_dir(app)?, id, change)?;
+    emit_note_changed(app, &summary);
+    Ok(summary)
+}
+
+fn update_metadata_in(
+    dir: &Path,
+    id: &str,
+    change: impl FnOnce(&mut StoredNoteMetadata),
+) -> Result<NoteSummary, AppError> {
+    let mut index = load_index_in(dir)?;
     let meta = index
+        .iter_mut()
+        .find(|meta| meta.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("Note {id} not found")))?;
+    change(meta);
+    let meta = meta.clone();
+    save_index_in(dir, &index)?;
+    Ok(build_summary_in(dir, meta))
+}
+
+/// Recomputes one note's preview from its file, e.g. after it changed on disk.
+/// Previews are derived rather than stored, so this only rebuilds and broadcasts the summary.
+#[tauri::command]
+fn refresh_preview(app: AppHandle, id: String) -> Result<NoteSummary, AppError> {
+    let summary = refresh_preview_in(&notes_dir(&app)?, &id)?;
+    emit_note_changed(&app, &summary);
+    Ok(summary)
+}
+
+fn refresh_preview_in(dir: &Path, id: &str) -> Result<NoteSummary, AppError> {
+    let meta = load_index_in(dir)?
+        .into_iter()
         .find(|meta| meta.id == id)
-        .ok_or_else(|| "Note metadata missing".to_string())?;
+        .ok_or_else(|| AppError::NotFound(format!("Note {id} not found")))?;
+    Ok(build_summary_in(dir, meta))
+}
+
+/// Named accents the note cards know how to render.
+const NOTE_COLORS: &[&str] = &["red", "orange", "yellow", "green", "blue", "purple", "pink", "gray"];
+
+/// Accepts a named accent or a `#rgb` / `#rrggbb` hex color, lowercased.
+fn validate_color(color: &str) -> Result<String, AppError> {
+    let color = color.trim().to_ascii_lowercase();
+    let is_hex = color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if is_hex || NOTE_COLORS.contains(&color.as_str()) {
+        Ok(color)
+    } else {
+        Err(AppError::InvalidInput(format!("Unsupported color: {color}")))
+    }
+}
+
+/// The label `set_note_color` stores: `None` clears it.
+fn note_color(color: Option<String>) -> Result<Option<String>, AppError> {
+    color.filter(|c| !c.trim().is_empty()).map(|c| validate_color(&c)).transpose()
+}
+
+/// Sets or, with `None` or an empty string, clears a note's color label.
+#[tauri::command]
+fn set_note_color(app: AppHandle, id: String, color: Option<String>) -> Result<NoteSummary, AppError> {
+    let color = note_color(color)?;
+    update_metadata(&app, &id, |meta| meta.color = color)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RenamedNote {
+    #[serde(flatten)]
+    note: NoteSummary,
+    /// `[[Old Title]]` links rewritten to the new title.
+    updated_links: usize,
+}
+
+/// Changes only the title in the index; the note file isn't rewritten. The
+/// title is taken as given, without falling back to one derived from the content.
+/// With `update_links`, other notes' `[[Old Title]]` links follow the rename.
+#[tauri::command]
+fn rename_note(app: AppHandle, id: String, title: String, update_links: Option<bool>) -> Result<RenamedNote, AppError> {
+    let (note, old_title) = {
+        let _guard = lock_index(&app)?;
+        let renamed = rename_note_in(&notes_dir(&app)?, &id, &title)?;
+        emit_note_changed(&app, &renamed.0);
+        renamed
+    };
+    let title = note.title.clone();
+    let updated_links = if update_links.unwrap_or(false) && old_title.to_lowercase() != title.to_lowercase() {
+        links::rename_title_links(&app, &id, &old_title, &title)?
+    } else {
+        0
+    };
+    Ok(RenamedNote { note, updated_links })
+}
+
+/// Sets the title of note `id` in `dir`'s index, returning the new summary and the old title.
+fn rename_note_in(dir: &Path, id: &str, title: &str) -> Result<(NoteSummary, String), AppError> {
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Err(AppError::InvalidInput("Title cannot be empty".into()));
+    }
+    let mut old_title = String::new();
+    let note = update_metadata_in(dir, id, |meta| {
+        old_title = std::mem::replace(&mut meta.title, title);
+        meta.updated_at = now_timestamp();
+    })?;
+    Ok((note, old_title))
+}
+
+#[tauri::command]
+fn archive_note(app: AppHandle, id: String) -> Result<NoteSummary, AppError> {
+    update_metadata(&app, &id, |meta| meta.archived = true)
+}
+
+#[tauri::command]
+fn unarchive_note(app: AppHandle, id: String) -> Result<NoteSummary, AppError> {
+    update_metadata(&app, &id, |meta| meta.archived = false)
+}
+
+/// A note whose file vanished fails with `missingContent` rather than a bare OS
+/// error; `delete_note` or `compact_index` can then drop the stale entry.
+#[tauri::command]
+fn load_note(app: AppHandle, id: String) -> Result<NoteDocument, AppError> {
+    load_note_in(&notes_dir(&app)?, id)
+}
+
+fn load_note_in(dir: &Path, id: String) -> Result<NoteDocument, AppError> {
+    let meta = load_index_in(dir)?
+        .into_iter()
+        .find(|meta| meta.id == id)
+        .ok_or_else(|| AppError::NotFound("Note metadata missing".into()))?;
+    let content = match fs::read_to_string(note_file(dir, &id)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::MissingContent(format!(
+                "Note content file for \"{}\" is missing; the note may be corrupted",
+                meta.title
+            )));
+        }
+        result => result?,
+    };
 
     Ok(NoteDocument {
         id,
         title: meta.title,
         content,
+        // Notes from before `created_at` was recorded were backfilled on migration;
+        // anything still missing is treated as created at its last update.
+        created_at: meta.created_at.or_else(|| Some(meta.updated_at.clone())),
         updated_at: meta.updated_at,
+        color: meta.color,
+        tags: meta.tags,
+        archived: meta.archived,
     })
 }
 
+/// Fills in a missing title and timestamp and checks the id, without touching disk.
+fn prepare_note(note: &mut NoteDocument, index: &[StoredNoteMetadata]) -> Result<(), AppError> {
+    if note.title.trim().is_empty() {
+        note.title = derive_title(&note.content);
+    }
+    note.updated_at = if note.updated_at.trim().is_empty() {
+        now_timestamp()
+    } else {
+        normalize_timestamp(&note.updated_at)
+            .ok_or_else(|| AppError::InvalidInput(format!("Invalid timestamp: {}", note.updated_at)))?
+    };
+
+    // New ids must be UUIDs; anything else could name an arbitrary file or collide.
+    let known = index.iter().any(|meta| meta.id == note.id);
+    if !known && Uuid::parse_str(&note.id).is_err() {
+        return Err(AppError::InvalidId(format!("Invalid note id: {}", note.id)));
+    }
+    Ok(())
+}
+
 #[tauri::command]
-fn save_note(app: AppHandle, note: NoteDocument) -> Result<NoteSummary, String> {
-    let path = note_path(&app, &note.id)?;
-    fs::write(path, &note.content).map_err(|e| e.to_string())?;
+fn save_note(app: AppHandle, note: NoteDocument) -> Result<NoteSummary, AppError> {
+    let _guard = lock_index(&app)?;
+    let summary = save_note_in(&notes_dir(&app)?, note, settings::load_settings(&app).note_history_limit)?;
+    emit_note_changed(&app, &summary);
+    Ok(summary)
+}
 
-    let mut index = load_index(&app)?;
-    if let Some(existing) = index.iter_mut().find(|meta| meta.id == note.id) {
+/// `save_note` for a known notes folder; the caller holds the index lock.
+fn save_note_in(dir: &Path, mut note: NoteDocument, history_limit: usize) -> Result<NoteSummary, AppError> {
+    let mut index = load_index_in(dir)?;
+    prepare_note(&mut note, &index)?;
+    let summary = write_note(dir, history_limit, &mut index, note)?;
+    save_index_in(dir, &index)?;
+    Ok(summary)
+}
+
+/// Saves several notes, writing `index.json` once. Every note is checked before
+/// any file is written, so one bad note fails the batch without changing anything.
+#[tauri::command]
+fn save_notes(app: AppHandle, notes: Vec<NoteDocument>) -> Result<Vec<NoteSummary>, AppError> {
+    let _guard = lock_index(&app)?;
+    let history_limit = settings::load_settings(&app).note_history_limit;
+    let (summaries, result) = save_notes_in(&notes_dir(&app)?, notes, history_limit);
+    for summary in &summaries {
+        emit_note_changed(&app, summary);
+    }
+    result.map(|()| summaries)
+}
+
+/// The notes `save_notes` wrote, even when it then failed, and whether it succeeded.
+fn save_notes_in(dir: &Path, mut notes: Vec<NoteDocument>, history_limit: usize) -> (Vec<NoteSummary>, Result<(), AppError>) {
+    let mut summaries = Vec::with_capacity(notes.len());
+    let result = (|| {
+        let mut index = load_index_in(dir)?;
+        for note in notes.iter_mut() {
+            prepare_note(note, &index)?;
+        }
+
+        let mut failed = None;
+        for note in notes {
+            match write_note(dir, history_limit, &mut index, note) {
+                Ok(summary) => summaries.push(summary),
+                Err(err) => {
+                    failed = Some(err);
+                    break;
+                }
+            }
+        }
+        // Even after a failed write the index is saved, so it matches the files that were written.
+        if !summaries.is_empty() {
+            save_index_in(dir, &index)?;
+        }
+        failed.map_or(Ok(()), Err)
+    })();
+    (summaries, result)
+}
+
+/// Writes a prepared note's file in `dir` and updates its entry in `index`,
+/// keeping up to `history_limit` old versions. The caller saves the index.
+fn write_note(dir: &Path, history_limit: usize, index: &mut Vec<StoredNoteMetadata>, note: NoteDocument) -> Result<NoteSummary, AppError> {
+    let path = note_file(dir, &note.id);
+    // Saving unchanged content shouldn't push a real version out of the history.
+    if fs::read(&path).is_ok_and(|old| old != note.content.as_bytes()) {
+        versions::snapshot_in(dir, &note.id, history_limit)?;
+    }
+    watcher::mark_own_write(&note.id);
+    fs::write(path, &note.content)?;
+
+    let (color, tags, archived) = if let Some(existing) = index.iter_mut().find(|meta| meta.id == note.id) {
         existing.title = note.title.clone();
         existing.updated_at = note.updated_at.clone();
+        (existing.color.clone(), existing.tags.clone(), existing.archived)
     } else {
         index.push(StoredNoteMetadata {
             id: note.id.clone(),
             title: note.title.clone(),
             updated_at: note.updated_at.clone(),
+            created_at: Some(note.updated_at.clone()),
+            color: None,
+            tags: Vec::new(),
+            archived: false,
+            extension: None,
         });
-    }
-    save_index(&app, &index)?;
-
-    let preview = preview_from_content(&note.content);
+        (None, Vec::new(), false)
+    };
 
     Ok(NoteSummary {
+        preview: preview_from_content(&note.content),
         id: note.id,
         title: note.title,
         updated_at: note.updated_at,
-        preview,
+        color,
+        tags,
+        archived,
     })
 }
 
+/// `extension` picks the file format, e.g. `txt` for a plain-text note; markdown by default.
 #[tauri::command]
-fn delete_note(app: AppHandle, id: String) -> Result<(), String> {
-    if let Ok(path) = note_path(&app, &id) {
+fn create_note(app: AppHandle, title: String, content: String, extension: Option<String>) -> Result<NoteSummary, AppError> {
+    let extension = validate_extension(extension)?;
+    let _guard = lock_index(&app)?;
+    let summary = create_note_in(&notes_dir(&app)?, title, &content, extension)?;
+    emit_note_changed(&app, &summary);
+    Ok(summary)
+}
+
+fn create_note_in(dir: &Path, title: String, content: &str, extension: Option<String>) -> Result<NoteSummary, AppError> {
+    let mut index = load_index_in(dir)?;
+    let now = now_timestamp();
+    let title = if title.trim().is_empty() { derive_title(content) } else { title };
+    let meta = StoredNoteMetadata {
+        id: Uuid::new_v4().to_string(),
+        title,
+        updated_at: now.clone(),
+        created_at: Some(now),
+        color: None,
+        tags: Vec::new(),
+        archived: false,
+        extension,
+    };
+    watcher::mark_own_write(&meta.id);
+    fs::write(dir.join(meta.file_name()), content)?;
+    index.push(meta.clone());
+    save_index_in(dir, &index)?;
+
+    Ok(NoteSummary {
+        id: meta.id,
+        title: meta.title,
+        updated_at: meta.updated_at,
+        preview: preview_from_content(content),
+        color: meta.color,
+        tags: meta.tags,
+        archived: meta.archived,
+    })
+}
+
+#[tauri::command]
+fn duplicate_note(app: AppHandle, id: String) -> Result<NoteSummary, AppError> {
+    let _guard = lock_index(&app)?;
+    let summary = duplicate_in(&notes_dir(&app)?, &id)?;
+    emit_note_changed(&app, &summary);
+    Ok(summary)
+}
+
+/// `duplicate_note` for a known notes folder; the caller holds the index lock.
+fn duplicate_in(dir: &Path, id: &str) -> Result<NoteSummary, AppError> {
+    let mut index = load_index_in(dir)?;
+    let source = index
+        .iter()
+        .find(|meta| meta.id == id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound("Note metadata missing".into()))?;
+    // Refuse to produce an empty copy when the original's content is gone.
+    let content = fs::read_to_string(note_file(dir, id))?;
+
+    let now = now_timestamp();
+    let copy = StoredNoteMetadata {
+        id: Uuid::new_v4().to_string(),
+        title: format!("{} (copy)", source.title),
+        updated_at: now.clone(),
+        created_at: Some(now),
+        color: source.color,
+        tags: source.tags,
+        archived: false,
+        extension: source.extension,
+    };
+    watcher::mark_own_write(&copy.id);
+    fs::write(dir.join(copy.file_name()), &content)?;
+    index.push(copy.clone());
+    save_index_in(dir, &index)?;
+
+    Ok(NoteSummary {
+        id: copy.id,
+        title: copy.title,
+        updated_at: copy.updated_at,
+        preview: preview_from_content(&content),
+        color: copy.color,
+        tags: copy.tags,
+        archived: copy.archived,
+    })
+}
+
+#[tauri::command]
+fn delete_note(app: AppHandle, id: String) -> Result<(), AppError> {
+    let _guard = lock_index(&app)?;
+    delete_note_in(&notes_dir(&app)?, &id)?;
+    let _ = app.emit("note://deleted", note_deleted_payload(&id));
+    Ok(())
+}
+
+/// Deletes one note from `dir`. Only an id the index lists is touched, so an id
+/// like `..` can't remove anything outside that note's own files.
+fn delete_note_in(dir: &Path, id: &str) -> Result<(), AppError> {
+    if !is_safe_id(id) {
+        return Err(AppError::InvalidId(format!("Invalid note id: {id}")));
+    }
+    let (deleted, _) = delete_notes_in(dir, vec![id.to_string()])?;
+    if deleted.is_empty() {
+        return Err(AppError::NotFound("Note metadata missing".into()));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeleteNotesResult {
+    deleted: usize,
+    not_found: Vec<String>,
+}
+
+/// Deletes several notes, rewriting the index once instead of once per note.
+#[tauri::command]
+fn delete_notes(app: AppHandle, ids: Vec<String>) -> Result<DeleteNotesResult, AppError> {
+    let _guard = lock_index(&app)?;
+    let (deleted, not_found) = delete_notes_in(&notes_dir(&app)?, ids)?;
+    for id in &deleted {
+        let _ = app.emit("note://deleted", note_deleted_payload(id));
+    }
+
+    Ok(DeleteNotesResult { deleted: deleted.len(), not_found })
+}
+
+/// Deletes the indexed notes among `ids` from `dir`. Returns the ids that were
+/// deleted and the ones the index doesn't list, which are left alone.
+fn delete_notes_in(dir: &Path, mut ids: Vec<String>) -> Result<(Vec<String>, Vec<String>), AppError> {
+    ids.sort();
+    ids.dedup();
+    let mut index = load_index_in(dir)?;
+    let (found, not_found): (Vec<String>, Vec<String>) = ids
+        .into_iter()
+        .partition(|id| index.iter().any(|meta| &meta.id == id));
+
+    for id in &found {
+        let path = note_file(dir, id);
         if path.exists() {
-            if let Err(err) = fs::remove_file(path) {
-                return Err(err.to_string());
-            }
+            watcher::mark_own_write(id);
+            fs::remove_file(path)?;
         }
     }
+    for id in &found {
+        attachments::remove_attachments(dir, id);
+        versions::remove_versions(dir, id);
+    }
 
-    let mut index = load_index(&app)?;
-    let len_before = index.len();
-    index.retain(|meta| meta.id != id);
-    if index.len() != len_before {
-        save_index(&app, &index)?;
+    index.retain(|meta| !found.contains(&meta.id));
+    if !found.is_empty() {
+        save_index_in(dir, &index)?;
     }
+    Ok((found, not_found))
+}
 
-    Ok(())
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EmptyNotes {
+    /// Notes whose content is empty or only whitespace.
+    ids: Vec<String>,
+    deleted: usize,
+}
+
+/// Finds notes with no content besides whitespace. They are only reported
+/// unless `delete` is set, so the UI can confirm before anything is removed.
+#[tauri::command]
+fn prune_empty_notes(app: AppHandle, delete: Option<bool>) -> Result<EmptyNotes, AppError> {
+    let ids = empty_note_ids(&notes_dir(&app)?)?;
+    let deleted = if delete.unwrap_or(false) && !ids.is_empty() {
+        delete_notes(app, ids.clone())?.deleted
+    } else {
+        0
+    };
+    Ok(EmptyNotes { ids, deleted })
+}
+
+/// Notes in `dir` whose file holds only whitespace. Missing files aren't counted.
+fn empty_note_ids(dir: &Path) -> Result<Vec<String>, AppError> {
+    Ok(load_index_in(dir)?
+        .into_iter()
+        .filter(|meta| read_note_lossy(&note_file(dir, &meta.id)).is_ok_and(|content| content.trim().is_empty()))
+        .map(|meta| meta.id)
+        .collect())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompactReport {
+    /// Entries dropped because their note file no longer exists.
+    dangling: Vec<String>,
+    /// Ids that appeared more than once; only the newest entry was kept.
+    duplicates: Vec<String>,
+}
+
+/// Repairs the index: drops entries without a note file and collapses repeated ids.
+#[tauri::command]
+fn compact_index(app: AppHandle) -> Result<CompactReport, AppError> {
+    let _guard = lock_index(&app)?;
+    let report = compact_index_in(&notes_dir(&app)?)?;
+    for id in &report.dangling {
+        let _ = app.emit("note://deleted", note_deleted_payload(id));
+    }
+    Ok(report)
+}
+
+fn compact_index_in(dir: &Path) -> Result<CompactReport, AppError> {
+    let index = load_index_in(dir)?;
+    let before = index.len();
+    let mut report = CompactReport { dangling: Vec::new(), duplicates: Vec::new() };
+
+    let mut kept: Vec<StoredNoteMetadata> = Vec::with_capacity(before);
+    for meta in index {
+        if !note_file(dir, &meta.id).exists() {
+            if !report.dangling.contains(&meta.id) {
+                report.dangling.push(meta.id);
+            }
+            continue;
+        }
+        match kept.iter_mut().find(|existing| existing.id == meta.id) {
+            Some(existing) => {
+                if !report.duplicates.contains(&meta.id) {
+                    report.duplicates.push(meta.id.clone());
+                }
+                if parse_timestamp(&meta.updated_at) > parse_timestamp(&existing.updated_at) {
+                    *existing = meta;
+                }
+            }
+            None => kept.push(meta),
+        }
+    }
+
+    if kept.len() != before {
+        save_index_in(dir, &kept)?;
+    }
+    Ok(report)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // Also claims the notes folder for this instance. An index that can't be
+            // upgraded still loads; commands report the real problem.
+            let _ = upgrade_index(app.handle());
+            apply_preview_settings(&settings::load_settings(app.handle()));
+            // Scratch files are only ours to remove once we hold the notes lock.
+            if let Ok(_guard) = lock_index(app.handle()) {
+                share::clean_transfer_leftovers(app.handle());
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             list_notes,
+            set_notes_directory,
+            refresh_preview,
+            rename_note,
+            set_note_color,
+            archive_note,
+            unarchive_note,
+            list_archived,
             load_note,
             save_note,
+            save_notes,
+            create_note,
             delete_note,
+            delete_notes,
+            compact_index,
+            prune_empty_notes,
+            integrity::check_integrity,
+            duplicate_note,
+            attachments::save_attachment,
+            attachments::list_attachments,
+            attachments::delete_attachment,
+            export::export_note_html,
+            export::export_combined_markdown,
+            export::note_as_plaintext,
+            history::list_transfers,
+            import::import_markdown_dir,
+            links::resolve_links,
+            links::backlinks,
+            stats::note_stats,
+            stats::notes_summary_stats,
+            tags::set_note_tags,
+            tags::suggest_tags,
+            templates::list_templates,
+            templates::save_template,
+            templates::create_note_from_template,
+            templates::daily_note,
+            toc::note_toc,
+            versions::list_note_versions,
+            versions::restore_note_version,
+            watcher::start_watching_notes,
             share::start_receive_service,
             share::send_all_notes,
             share::discover_receivers,
             share::send_all_notes_to,
+            share::send_all_notes_to_many,
             share::send_note_to,
             share::start_send_all_notes_to,
             share::start_send_note_to,
+            share::send_selected_notes_to,
+            share::start_send_selected_notes_to,
             share::accept_incoming_transfer,
-            share::stop_receive_service
+            share::stop_receive_service,
+            share::export_notes_zip,
+            share::import_notes_zip,
+            share::start_discovery,
+            share::stop_discovery,
+            share::device_info,
+            share::list_interfaces,
+            share::diagnose_discovery,
+            share::set_source_ip,
+            share::get_device_name,
+            share::set_device_name,
+            share::set_discoverable,
+            settings::get_settings,
+            settings::update_settings,
+            share::list_trusted_peers,
+            share::trust_peer,
+            share::untrust_peer,
+            share::list_favorite_peers,
+            share::add_favorite_peer,
+            share::remove_favorite_peer,
+            share::send_all_notes_to_favorite,
+            share::set_transfer_passphrase,
+            search::search_notes,
+            search::fuzzy_search_notes,
+            search::replace_across_notes,
+            pairing::pairing_code,
+            pairing::connect_from_pairing
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_title_uses_the_first_heading_or_line() {
+        assert_eq!(derive_title("## Shopping list\n- milk\n"), "Shopping list");
+        assert_eq!(derive_title("\n  \n\tPlain first line  \nmore"), "Plain first line");
+        assert_eq!(derive_title("#\n###   \nAfter empty headings"), "After empty headings");
+    }
+
+    #[test]
+    fn derive_title_falls_back_to_untitled() {
+        assert_eq!(derive_title(""), "Untitled");
+        assert_eq!(derive_title(" \n\n# \n"), "Untitled");
+    }
+
+    #[test]
+    fn derive_title_shortens_a_long_first_line() {
+        let title = derive_title(&format!("# {}", "é".repeat(DERIVED_TITLE_MAX_CHARS + 20)));
+        assert_eq!(title, format!("{}...", "é".repeat(DERIVED_TITLE_MAX_CHARS)));
+    }
+
+    #[test]
+    fn duplicate_copies_content_and_metadata_under_a_new_id() {
+        let dir = testing::notes_folder(&[("a", "Plan", "# Plan\nsteps")]);
+        let copy = duplicate_in(dir.path(), "a").unwrap();
+        assert_ne!(copy.id, "a");
+        assert_eq!(copy.title, "Plan (copy)");
+        assert_eq!(fs::read_to_string(note_file(dir.path(), &copy.id)).unwrap(), "# Plan\nsteps");
+        let ids: Vec<String> = load_index_in(dir.path()).unwrap().into_iter().map(|meta| meta.id).collect();
+        assert_eq!(ids, ["a".to_string(), copy.id]);
+    }
+
+    #[test]
+    fn duplicate_refuses_a_note_whose_file_is_gone() {
+        let dir = testing::notes_folder(&[("a", "Plan", "")]);
+        fs::remove_file(note_file(dir.path(), "a")).unwrap();
+        assert!(matches!(duplicate_in(dir.path(), "a"), Err(AppError::NotFound(_))));
+        assert!(matches!(duplicate_in(dir.path(), "missing"), Err(AppError::NotFound(_))));
+        assert_eq!(load_index_in(dir.path()).unwrap().len(), 1);
+    }
+
+    fn document(id: &str, content: &str) -> NoteDocument {
+        serde_json::from_value(serde_json::json!({ "id": id, "title": "", "content": content, "updatedAt": "" })).unwrap()
+    }
+
+    #[test]
+    fn prepare_note_only_accepts_known_ids_or_new_uuids() {
+        let index = [testing::meta("legacy-id", "Old")];
+        assert!(prepare_note(&mut document("legacy-id", "x"), &index).is_ok());
+        assert!(prepare_note(&mut document(&Uuid::new_v4().to_string(), "x"), &index).is_ok());
+        for id in ["", "new-note", "../escape", "a/b"] {
+            assert!(matches!(prepare_note(&mut document(id, "x"), &index), Err(AppError::InvalidId(_))), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn change_events_carry_the_id_and_summary() {
+        let summary: NoteSummary = serde_json::from_value(serde_json::json!({
+            "id": "a", "title": "Plan", "updatedAt": "2024-01-01T00:00:00Z", "preview": "steps", "tags": ["work"]
+        }))
+        .unwrap();
+        let payload = note_changed_payload(&summary);
+        assert_eq!(payload["id"], "a");
+        assert_eq!(payload["summary"]["title"], "Plan");
+        assert_eq!(payload["summary"]["updatedAt"], "2024-01-01T00:00:00Z");
+        assert_eq!(payload["summary"]["tags"], serde_json::json!(["work"]));
+        assert_eq!(note_deleted_payload("a"), serde_json::json!({ "id": "a" }));
+    }
+
+    #[test]
+    fn refresh_preview_reads_the_current_file() {
+        let dir = testing::notes_folder(&[("a", "Plan", "old text")]);
+        fs::write(note_file(dir.path(), "a"), "\n\nedited elsewhere\n").unwrap();
+        let summary = refresh_preview_in(dir.path(), "a").unwrap();
+        assert_eq!((summary.title.as_str(), summary.preview.as_str()), ("Plan", "edited elsewhere"));
+        assert!(matches!(refresh_preview_in(dir.path(), "missing"), Err(AppError::NotFound(_))));
+    }
+
+    /// Index writes this thread makes while running `f`.
+    fn index_writes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = INDEX_WRITES.with(|writes| writes.get());
+        let result = f();
+        (result, INDEX_WRITES.with(|writes| writes.get()) - before)
+    }
+
+    #[test]
+    fn delete_notes_removes_listed_notes_with_one_index_write() {
+        let dir = testing::notes_folder(&[("a", "A", "a"), ("b", "B", "b"), ("c", "C", "c")]);
+        fs::create_dir_all(attachments::attachment_dir(dir.path(), "a")).unwrap();
+        let ids = ["a", "c", "a", "missing"].map(String::from).to_vec();
+        let ((deleted, not_found), writes) = index_writes(|| delete_notes_in(dir.path(), ids).unwrap());
+        assert_eq!((deleted, not_found), (vec!["a".to_string(), "c".to_string()], vec!["missing".to_string()]));
+        assert_eq!(writes, 1);
+        assert!(!note_file(dir.path(), "a").exists() && !note_file(dir.path(), "c").exists());
+        assert!(!attachments::attachment_dir(dir.path(), "a").exists());
+        let left: Vec<String> = load_index_in(dir.path()).unwrap().into_iter().map(|meta| meta.id).collect();
+        assert_eq!(left, ["b"]);
+    }
+
+    #[test]
+    fn delete_notes_leaves_files_the_index_doesnt_list() {
+        let dir = testing::notes_folder(&[("a", "A", "a")]);
+        fs::write(dir.path().join("stray.md"), "keep").unwrap();
+        let ((deleted, not_found), writes) = index_writes(|| delete_notes_in(dir.path(), vec!["stray".into()]).unwrap());
+        assert!(deleted.is_empty());
+        assert_eq!(not_found, ["stray"]);
+        assert_eq!(writes, 0);
+        assert!(dir.path().join("stray.md").exists());
+    }
+
+    #[test]
+    fn delete_note_refuses_ids_that_reach_outside_the_note() {
+        let dir = testing::notes_folder(&[("a", "A", "a")]);
+        fs::create_dir_all(attachments::attachment_dir(dir.path(), "a")).unwrap();
+        fs::create_dir_all(dir.path().join(versions::HISTORY_DIR).join("a")).unwrap();
+        for id in ["..", "", ".", "../a", "a/..", "a\\b"] {
+            assert!(matches!(delete_note_in(dir.path(), id), Err(AppError::InvalidId(_))), "{id:?}");
+            attachments::remove_attachments(dir.path(), id);
+            versions::remove_versions(dir.path(), id);
+        }
+        assert!(matches!(delete_note_in(dir.path(), "unknown"), Err(AppError::NotFound(_))));
+        assert!(note_file(dir.path(), "a").exists());
+        assert!(attachments::attachment_dir(dir.path(), "a").exists());
+        assert!(dir.path().join(versions::HISTORY_DIR).join("a").exists());
+        assert_eq!(load_index_in(dir.path()).unwrap().len(), 1);
+
+        delete_note_in(dir.path(), "a").unwrap();
+        assert!(!note_file(dir.path(), "a").exists());
+        assert!(!attachments::attachment_dir(dir.path(), "a").exists());
+        assert!(dir.path().exists());
+    }
+
+    #[test]
+    fn normalize_timestamp_converts_offsets_to_utc() {
+        assert_eq!(normalize_timestamp("2024-03-01T12:00:00+02:00").as_deref(), Some("2024-03-01T10:00:00Z"));
+        assert_eq!(normalize_timestamp("2024-03-01T10:00:00Z").as_deref(), Some("2024-03-01T10:00:00Z"));
+        assert_eq!(normalize_timestamp(" 2024-03-01 10:00:00 ").as_deref(), Some("2024-03-01T10:00:00Z"));
+        assert_eq!(normalize_timestamp("yesterday"), None);
+        assert_eq!(normalize_timestamp(""), None);
+    }
+
+    #[test]
+    fn custom_notes_directory_overrides_the_default() {
+        let default = PathBuf::from("/data/notes");
+        assert_eq!(chosen_notes_dir(None, default.clone()), default);
+        assert_eq!(chosen_notes_dir(Some("/sync/notes".into()), default), PathBuf::from("/sync/notes"));
+    }
+
+    #[test]
+    fn check_writable_creates_the_folder_and_leaves_no_probe() {
+        let dir = testing::TempDir::new();
+        let target = dir.path().join("nested").join("notes");
+        check_writable(&target).unwrap();
+        assert!(target.is_dir());
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+
+        let file = dir.path().join("file");
+        fs::write(&file, "x").unwrap();
+        assert!(matches!(check_writable(&file.join("notes")), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn migrate_notes_moves_only_notes_data() {
+        let from = testing::notes_folder(&[("a", "A", "alpha")]);
+        fs::create_dir_all(attachments::attachment_dir(from.path(), "a")).unwrap();
+        fs::write(from.path().join("unrelated.json"), "keep").unwrap();
+        let to = testing::TempDir::new();
+
+        migrate_notes(from.path(), to.path()).unwrap();
+        assert_eq!(fs::read_to_string(note_file(to.path(), "a")).unwrap(), "alpha");
+        assert_eq!(load_index_in(to.path()).unwrap().len(), 1);
+        assert!(attachments::attachment_dir(to.path(), "a").exists());
+        assert!(from.path().join("unrelated.json").exists());
+        assert!(!from.path().join(INDEX_FILE).exists());
+
+        assert!(matches!(migrate_notes(from.path(), to.path()), Err(AppError::Conflict(_))));
+    }
+
+    #[test]
+    fn compact_index_drops_entries_without_a_file() {
+        let dir = testing::notes_folder(&[("a", "A", "a"), ("b", "B", "b")]);
+        fs::remove_file(note_file(dir.path(), "b")).unwrap();
+
+        let report = compact_index_in(dir.path()).unwrap();
+        assert_eq!(report.dangling, ["b"]);
+        assert!(report.duplicates.is_empty());
+        let ids: Vec<String> = load_index_in(dir.path()).unwrap().into_iter().map(|meta| meta.id).collect();
+        assert_eq!(ids, ["a"]);
+    }
+
+    #[test]
+    fn compact_index_keeps_the_newest_of_repeated_ids() {
+        let dir = testing::notes_folder(&[("a", "A", "a")]);
+        let mut newer = testing::meta("a", "Newer");
+        newer.updated_at = "2024-06-01T00:00:00Z".into();
+        let older = testing::meta("a", "Older");
+        save_index_in(dir.path(), &[older, newer]).unwrap();
+
+        let report = compact_index_in(dir.path()).unwrap();
+        assert_eq!(report.duplicates, ["a"]);
+        let index = load_index_in(dir.path()).unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[0].title, "Newer");
+
+        // A clean index is left untouched.
+        assert_eq!(index_writes(|| compact_index_in(dir.path()).unwrap()).1, 0);
+    }
+
+    #[test]
+    fn validate_color_accepts_named_and_hex_colors() {
+        assert_eq!(validate_color(" Blue ").unwrap(), "blue");
+        assert_eq!(validate_color("#ABC").unwrap(), "#abc");
+        assert_eq!(validate_color("#00ff7f").unwrap(), "#00ff7f");
+        for bad in ["teal", "#abcd", "#ggg", "abc", "", "red; background: url(x)"] {
+            assert!(matches!(validate_color(bad), Err(AppError::InvalidInput(_))), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn note_color_can_be_set_and_cleared_across_reloads() {
+        let dir = testing::notes_folder(&[("a", "A", "a")]);
+        let color = note_color(Some("Red".into())).unwrap();
+        let summary = update_metadata_in(dir.path(), "a", |meta| meta.color = color).unwrap();
+        assert_eq!(summary.color.as_deref(), Some("red"));
+        assert_eq!(load_index_in(dir.path()).unwrap()[0].color.as_deref(), Some("red"));
+
+        let cleared = note_color(Some("  ".into())).unwrap();
+        assert_eq!(cleared, None);
+        update_metadata_in(dir.path(), "a", |meta| meta.color = cleared).unwrap();
+        assert_eq!(load_index_in(dir.path()).unwrap()[0].color, None);
+        assert!(!fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap().contains("color"));
+    }
+
+    /// A library of notes `n0`..`n4`, each updated a day after the previous one,
+    /// plus an archived note.
+    fn dated_library() -> testing::TempDir {
+        let dir = testing::notes_folder(&[("n0", "", ""), ("n1", "", ""), ("n2", "", ""), ("n3", "", ""), ("n4", "", ""), ("old", "", "")]);
+        let mut index = load_index_in(dir.path()).unwrap();
+        for (day, meta) in index.iter_mut().enumerate() {
+            meta.updated_at = format!("2024-01-0{}T00:00:00Z", day + 1);
+        }
+        index[5].archived = true;
+        save_index_in(dir.path(), &index).unwrap();
+        dir
+    }
+
+    fn page_ids(page: &NotesPage) -> Vec<&str> {
+        page.notes.iter().map(|note| note.id.as_str()).collect()
+    }
+
+    #[test]
+    fn list_notes_pages_through_the_sorted_notes() {
+        let dir = dated_library();
+        let first = list_notes_in(dir.path(), Some(0), Some(2), None).unwrap();
+        assert_eq!(page_ids(&first), ["n4", "n3"]);
+        assert_eq!(first.total, 5);
+        let last = list_notes_in(dir.path(), Some(4), Some(2), None).unwrap();
+        assert_eq!(page_ids(&last), ["n0"]);
+        assert_eq!(last.total, 5);
+        let past_the_end = list_notes_in(dir.path(), Some(10), Some(2), None).unwrap();
+        assert!(past_the_end.notes.is_empty());
+        assert_eq!(past_the_end.total, 5);
+    }
+
+    #[test]
+    fn list_notes_without_a_page_returns_every_unarchived_note() {
+        let dir = dated_library();
+        let all = list_notes_in(dir.path(), None, None, None).unwrap();
+        assert_eq!(page_ids(&all), ["n4", "n3", "n2", "n1", "n0"]);
+        assert_eq!(all.total, 5);
+    }
+
+    #[test]
+    fn concurrent_saves_keep_every_note() {
+        let dir = testing::notes_folder(&[]);
+        let path = dir.path().to_path_buf();
+        let ids: Vec<String> = (0..8).map(|_| Uuid::new_v4().to_string()).collect();
+        let savers: Vec<_> = ids
+            .iter()
+            .cloned()
+            .map(|id| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let _guard = lock_notes_dir(&path).unwrap();
+                    save_note_in(&path, document(&id, &id), 5).unwrap();
+                })
+            })
+            .collect();
+        for saver in savers {
+            saver.join().unwrap();
+        }
+
+        let index = load_index_in(dir.path()).unwrap();
+        assert_eq!(index.len(), ids.len());
+        for id in &ids {
+            assert!(index.iter().any(|meta| &meta.id == id), "{id} missing");
+            assert_eq!(fs::read_to_string(note_file(dir.path(), id)).unwrap(), *id);
+        }
+    }
+
+    #[test]
+    fn load_note_reports_a_missing_file_clearly() {
+        let dir = testing::notes_folder(&[("a", "Plan", "steps"), ("b", "Lost", "gone")]);
+        fs::remove_file(note_file(dir.path(), "b")).unwrap();
+
+        assert_eq!(load_note_in(dir.path(), "a".into()).unwrap().content, "steps");
+        let Err(err) = load_note_in(dir.path(), "b".into()) else { panic!("loaded a note without a file") };
+        assert!(matches!(&err, AppError::MissingContent(_)));
+        assert_eq!(err.to_string(), "Note content file for \"Lost\" is missing; the note may be corrupted");
+        assert!(matches!(load_note_in(dir.path(), "c".into()), Err(AppError::NotFound(_))));
+
+        // The stale entry can then be dropped.
+        delete_note_in(dir.path(), "b").unwrap();
+        assert_eq!(load_index_in(dir.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn archived_notes_move_from_the_list_to_the_archive() {
+        let dir = testing::notes_folder(&[("a", "A", "a"), ("b", "B", "b")]);
+        let archived = update_metadata_in(dir.path(), "a", |meta| meta.archived = true).unwrap();
+        assert!(archived.archived);
+        assert_eq!(page_ids(&list_notes_in(dir.path(), None, None, None).unwrap()), ["b"]);
+        let archive: Vec<String> = list_archived_in(dir.path()).unwrap().into_iter().map(|note| note.id).collect();
+        assert_eq!(archive, ["a"]);
+
+        update_metadata_in(dir.path(), "a", |meta| meta.archived = false).unwrap();
+        assert_eq!(list_notes_in(dir.path(), None, None, None).unwrap().total, 2);
+        assert!(list_archived_in(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_entries_are_not_archived() {
+        let (index, _) = parse_index(r#"[{"id": "a", "title": "A", "updatedAt": "2024-01-01T00:00:00Z"}]"#).unwrap();
+        assert!(!index[0].archived);
+    }
+
+    #[test]
+    fn parse_index_migrates_a_legacy_array() {
+        let (notes, version) = parse_index(r#"[{"id":"a","title":"A","updatedAt":"2024-01-01T00:00:00Z"}]"#).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(notes[0].created_at.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn parse_index_reads_the_versioned_layout() {
+        let json = index_json(&parse_index(r#"[{"id":"a","title":"A","updatedAt":"2024-01-02T00:00:00Z","createdAt":"2024-01-01T00:00:00Z"}]"#).unwrap().0).unwrap();
+        let (notes, version) = parse_index(&json).unwrap();
+        assert_eq!(version, INDEX_VERSION);
+        assert_eq!(notes[0].id, "a");
+        assert_eq!(notes[0].created_at.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn parse_index_rejects_garbage() {
+        assert!(parse_index("{not json").is_err());
+    }
+
+    #[test]
+    fn rename_note_changes_only_the_index() {
+        let dir = testing::notes_folder(&[("a", "Old", "# Old\r\nbody without a trailing newline")]);
+        let before = fs::read(note_file(dir.path(), "a")).unwrap();
+        let modified = fs::metadata(note_file(dir.path(), "a")).unwrap().modified().unwrap();
+
+        let (note, old_title) = rename_note_in(dir.path(), "a", "  New title ").unwrap();
+        assert_eq!((note.title.as_str(), old_title.as_str()), ("New title", "Old"));
+        assert_ne!(note.updated_at, "2024-01-01T00:00:00Z");
+        assert_eq!(load_index_in(dir.path()).unwrap()[0].title, "New title");
+        assert_eq!(fs::read(note_file(dir.path(), "a")).unwrap(), before);
+        assert_eq!(fs::metadata(note_file(dir.path(), "a")).unwrap().modified().unwrap(), modified);
+
+        assert!(matches!(rename_note_in(dir.path(), "a", "   "), Err(AppError::InvalidInput(_))));
+        assert!(matches!(rename_note_in(dir.path(), "missing", "x"), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn damaged_note_files_still_read_lossily() {
+        let dir = testing::notes_folder(&[("a", "A", "")]);
+        fs::write(note_file(dir.path(), "a"), [b'h', b'i', 0xff, b'!']).unwrap();
+        assert_eq!(read_note_lossy(&note_file(dir.path(), "a")).unwrap(), "hi\u{fffd}!");
+        assert_eq!(preview_from_file(&note_file(dir.path(), "a")).unwrap(), "hi\u{fffd}!");
+        assert_eq!(list_notes_in(dir.path(), None, None, None).unwrap().notes[0].preview, "hi\u{fffd}!");
+    }
+
+    #[test]
+    fn empty_notes_are_only_the_whitespace_ones() {
+        let dir = testing::notes_folder(&[("blank", "", " \n\t\r\n"), ("empty", "", ""), ("dot", "", " . "), ("gone", "", "")]);
+        fs::remove_file(note_file(dir.path(), "gone")).unwrap();
+        assert_eq!(empty_note_ids(dir.path()).unwrap(), ["blank", "empty"]);
+        // Detection alone changes nothing.
+        assert_eq!(load_index_in(dir.path()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn validate_extension_defaults_to_markdown() {
+        assert_eq!(validate_extension(None).unwrap(), None);
+        assert_eq!(validate_extension(Some(".MD".into())).unwrap(), None);
+        assert_eq!(validate_extension(Some(" .TXT ".into())).unwrap().as_deref(), Some("txt"));
+        assert!(matches!(validate_extension(Some("exe".into())), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn txt_notes_are_saved_and_loaded_as_txt() {
+        let dir = testing::notes_folder(&[]);
+        let created = create_note_in(dir.path(), "Plain".into(), "first", Some("txt".into())).unwrap();
+        let path = dir.path().join(format!("{}.txt", created.id));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+        assert_eq!(load_index_in(dir.path()).unwrap()[0].extension.as_deref(), Some("txt"));
+
+        let mut note = load_note_in(dir.path(), created.id.clone()).unwrap();
+        assert_eq!(note.content, "first");
+        note.content = "second".into();
+        save_note_in(dir.path(), note, 5).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(!dir.path().join(format!("{}.md", created.id)).exists());
+    }
+
+    #[test]
+    fn preview_builder_follows_the_length_and_markdown_options() {
+        let preview = |max_len, strip_markdown| {
+            let mut builder = PreviewBuilder::new(max_len, strip_markdown);
+            for line in "# Title\n**bold** text here".lines() {
+                if !builder.push_line(line) {
+                    break;
+                }
+            }
+            builder.finish()
+        };
+        assert_eq!(preview(16, false), "# Title\n**bol...");
+        assert_eq!(preview(16, true), "Title\n\nbold t...");
+        assert_eq!(preview(200, true), "Title\n\nbold text here");
+    }
+
+    #[test]
+    fn load_note_carries_the_full_metadata() {
+        let dir = testing::notes_folder(&[("a", "Tagged", "body"), ("b", "Legacy", "old")]);
+        let mut index = load_index_in(dir.path()).unwrap();
+        index[0].tags = vec!["work".into(), "todo".into()];
+        index[0].color = Some("#ffcc00".into());
+        index[0].created_at = Some("2023-06-01T00:00:00Z".into());
+        index[1].created_at = None;
+        save_index_in(dir.path(), &index).unwrap();
+
+        let tagged = load_note_in(dir.path(), "a".into()).unwrap();
+        assert_eq!(tagged.tags, ["work", "todo"]);
+        assert_eq!(tagged.color.as_deref(), Some("#ffcc00"));
+        assert_eq!(tagged.created_at.as_deref(), Some("2023-06-01T00:00:00Z"));
+        assert!(!tagged.archived);
+
+        let legacy = load_note_in(dir.path(), "b".into()).unwrap();
+        assert!(legacy.tags.is_empty() && legacy.color.is_none());
+        assert_eq!(legacy.created_at.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn title_sort_key_ignores_case_accents_and_padding() {
+        assert_eq!(title_sort_key("  Äpfel "), "apfel");
+        let mut titles = ["Zebra", "Äpfel", "apple", "Émile"];
+        titles.sort_by_key(|title| title_sort_key(title));
+        assert_eq!(titles, ["Äpfel", "apple", "Émile", "Zebra"]);
+    }
+
+    #[test]
+    fn preview_from_file_matches_preview_from_content() {
+        let at_cutoff = format!("{}é tail\nsecond line", "a".repeat(PREVIEW_MAX_CHARS - 4));
+        let contents = [
+            "\n\n# Title\r\n\r\n- one\r\n- two   \n".to_string(),
+            at_cutoff.clone(),
+            "line\n".repeat(100),
+            String::new(),
+        ];
+        let dir = testing::TempDir::new();
+        let path = dir.path().join("note.md");
+        for content in &contents {
+            fs::write(&path, content).unwrap();
+            assert_eq!(preview_from_file(&path).unwrap(), preview_from_content(content));
+        }
+        // The cut lands inside the `é`, so it moves back to the character before it.
+        assert_eq!(preview_from_content(&at_cutoff), format!("{}...", "a".repeat(PREVIEW_MAX_CHARS - 4)));
+    }
+
+    #[test]
+    fn save_notes_writes_the_index_once_for_the_whole_batch() {
+        let dir = testing::notes_folder(&[]);
+        let notes: Vec<NoteDocument> = (0..10).map(|i| document(&Uuid::new_v4().to_string(), &format!("note {i}"))).collect();
+        let ((summaries, result), writes) = index_writes(|| save_notes_in(dir.path(), notes, 0));
+        assert!(result.is_ok());
+        assert_eq!((summaries.len(), writes), (10, 1));
+        assert_eq!(load_index_in(dir.path()).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn save_notes_writes_nothing_when_one_note_is_invalid() {
+        let dir = testing::notes_folder(&[]);
+        let notes = vec![document(&Uuid::new_v4().to_string(), "fine"), document("../escape", "bad")];
+        let ((summaries, result), writes) = index_writes(|| save_notes_in(dir.path(), notes, 0));
+        assert!(matches!(result, Err(AppError::InvalidId(_))));
+        assert!(summaries.is_empty());
+        assert_eq!(writes, 0);
+        assert!(load_index_in(dir.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{
+    build_summary_in, emit_note_changed, error::AppError, load_index_in, lock_index, note_file, notes_dir, now_timestamp,
+    read_note_lossy, save_index_in, settings::load_settings, versions, watcher, NoteSummary, StoredNoteMetadata,
+};
+
+/// A note file's mtime and the link targets parsed from it.
+type CachedLinks = (SystemTime, Vec<String>);
+
+/// Raw `[[...]]` targets per note file, invalidated by the file's mtime.
+/// Only the parse is cached; resolution always runs against the current index.
+static LINK_CACHE: Lazy<Mutex<HashMap<PathBuf, CachedLinks>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize)]
+pub struct ResolvedLink {
+    text: String,
+    id: String,
+}
+
+#[derive(Serialize)]
+pub struct NoteLinks {
+    resolved: Vec<ResolvedLink>,
+    unresolved: Vec<String>,
+}
+
+/// Targets of every `[[target]]` or `[[target|label]]` in `content`, in order of appearance.
+fn parse_links(content: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else { break };
+        let inner = &after[..end];
+        // A newline means this isn't a link, just stray brackets.
+        if !inner.contains('\n') {
+            let target = inner.split('|').next().unwrap_or("").trim();
+            if !target.is_empty() {
+                links.push(target.to_string());
+            }
+        }
+        rest = &after[end + 2..];
+    }
+    links
+}
+
+fn links_for(dir: &Path, id: &str) -> Result<Vec<String>, AppError> {
+    let path = note_file(dir, id);
+    let modified = fs::metadata(&path)?.modified()?;
+    if let Some((cached_at, links)) = LINK_CACHE.lock().unwrap().get(&path) {
+        if *cached_at == modified {
+            return Ok(links.clone());
+        }
+    }
+    let links = parse_links(&read_note_lossy(&path)?);
+    LINK_CACHE.lock().unwrap().insert(path, (modified, links.clone()));
+    Ok(links)
+}
+
+/// Rewrites every `[[old]]` or `[[old|label]]` in `content` to point at `new`,
+/// keeping labels. Returns the new content and how many links changed.
+fn retarget_links(content: &str, old: &str, new: &str) -> (String, usize) {
+    let lowered = old.to_lowercase();
+    let mut out = String::with_capacity(content.len());
+    let mut count = 0;
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else { break };
+        let inner = &after[..end];
+        out.push_str(&rest[..start + 2]);
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (inner, None),
+        };
+        if !inner.contains('\n') && target.trim().to_lowercase() == lowered {
+            out.push_str(new);
+            if let Some(label) = label {
+                out.push('|');
+                out.push_str(label);
+            }
+            count += 1;
+        } else {
+            out.push_str(inner);
+        }
+        out.push_str("]]");
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    (out, count)
+}
+
+/// After `id` was renamed from `old` to `new`, points title links that resolved
+/// to it at the new title. Links by id, and links that resolved to a different
+/// note sharing the old title, are left alone. Returns how many links changed.
+pub(crate) fn rename_title_links(app: &AppHandle, id: &str, old: &str, new: &str) -> Result<usize, AppError> {
+    let _guard = lock_index(app)?;
+    let dir = notes_dir(app)?;
+    let (total, changed) = rename_title_links_in(&dir, id, old, new, load_settings(app).note_history_limit)?;
+    for meta in changed {
+        emit_note_changed(app, &build_summary_in(&dir, meta));
+    }
+    Ok(total)
+}
+
+/// `rename_title_links` for a known notes folder. Each rewritten note keeps its
+/// previous content as a version, up to `history_limit`. Returns the link count
+/// and the entries of the notes that changed.
+fn rename_title_links_in(dir: &Path, id: &str, old: &str, new: &str, history_limit: usize) -> Result<(usize, Vec<StoredNoteMetadata>), AppError> {
+    let mut index = load_index_in(dir)?;
+    // `resolve` against the index as it was before the rename, when `old` was still a title.
+    let before: Vec<StoredNoteMetadata> = index
+        .iter()
+        .cloned()
+        .map(|mut meta| {
+            if meta.id == id {
+                meta.title = old.to_string();
+            }
+            meta
+        })
+        .collect();
+    if resolve(old, &before).is_none_or(|target| target.id != id) {
+        return Ok((0, Vec::new()));
+    }
+
+    let now = now_timestamp();
+    let mut total = 0;
+    let mut changed = Vec::new();
+    for meta in index.iter_mut() {
+        let path = note_file(dir, &meta.id);
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let (updated, count) = retarget_links(&content, old, new);
+        if count == 0 {
+            continue;
+        }
+        versions::snapshot_in(dir, &meta.id, history_limit)?;
+        watcher::mark_own_write(&meta.id);
+        fs::write(&path, updated)?;
+        meta.updated_at = now.clone();
+        total += count;
+        changed.push(meta.id.clone());
+    }
+    if changed.is_empty() {
+        return Ok((0, Vec::new()));
+    }
+    save_index_in(dir, &index)?;
+    Ok((total, index.into_iter().filter(|meta| changed.contains(&meta.id)).collect()))
+}
+
+/// Ids win over titles; titles match case-insensitively.
+fn resolve<'a>(target: &str, index: &'a [StoredNoteMetadata]) -> Option<&'a StoredNoteMetadata> {
+    let lowered = target.to_lowercase();
+    index
+        .iter()
+        .find(|meta| meta.id == target)
+        .or_else(|| index.iter().find(|meta| meta.title.to_lowercase() == lowered))
+}
+
+#[tauri::command]
+pub fn resolve_links(app: AppHandle, id: String) -> Result<NoteLinks, AppError> {
+    resolve_links_in(&notes_dir(&app)?, &id)
+}
+
+fn resolve_links_in(dir: &Path, id: &str) -> Result<NoteLinks, AppError> {
+    let index = load_index_in(dir)?;
+    if !index.iter().any(|meta| meta.id == id) {
+        return Err(AppError::NotFound("Note metadata missing".into()));
+    }
+    let mut result = NoteLinks { resolved: Vec::new(), unresolved: Vec::new() };
+    for text in links_for(dir, id)? {
+        match resolve(&text, &index) {
+            Some(meta) => result.resolved.push(ResolvedLink { id: meta.id.clone(), text }),
+            None => result.unresolved.push(text),
+        }
+    }
+    Ok(result)
+}
+
+/// Notes that contain a link resolving to `id`.
+#[tauri::command]
+pub fn backlinks(app: AppHandle, id: String) -> Result<Vec<NoteSummary>, AppError> {
+    let dir = notes_dir(&app)?;
+    Ok(linking_notes(&dir, &id)?.into_iter().map(|meta| build_summary_in(&dir, meta)).collect())
+}
+
+fn linking_notes(dir: &Path, id: &str) -> Result<Vec<StoredNoteMetadata>, AppError> {
+    let index = load_index_in(dir)?;
+    let mut linking = Vec::new();
+    for meta in &index {
+        if meta.id == id {
+            continue;
+        }
+        // A note whose file is missing can't link anywhere.
+        let Ok(links) = links_for(dir, &meta.id) else { continue };
+        if links.iter().any(|text| resolve(text, &index).is_some_and(|target| target.id == id)) {
+            linking.push(meta.clone());
+        }
+    }
+    Ok(linking)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::notes_folder;
+
+    fn ids(notes: &[StoredNoteMetadata]) -> Vec<&str> {
+        notes.iter().map(|meta| meta.id.as_str()).collect()
+    }
+
+    #[test]
+    fn resolve_prefers_ids_and_ignores_title_case() {
+        let index = [crate::testing::meta("a", "Plan"), crate::testing::meta("b", "a")];
+        assert_eq!(resolve("a", &index).map(|meta| meta.id.as_str()), Some("a"));
+        assert_eq!(resolve("PLAN", &index).map(|meta| meta.id.as_str()), Some("a"));
+        assert!(resolve("Missing", &index).is_none());
+    }
+
+    #[test]
+    fn resolve_links_reports_broken_links() {
+        let dir = notes_folder(&[("a", "Plan", "See [[Ideas|my ideas]], [[b]] and [[Nowhere]]."), ("b", "Ideas", "")]);
+        let links = resolve_links_in(dir.path(), "a").unwrap();
+        let resolved: Vec<(&str, &str)> = links.resolved.iter().map(|link| (link.text.as_str(), link.id.as_str())).collect();
+        assert_eq!(resolved, [("Ideas", "b"), ("b", "b")]);
+        assert_eq!(links.unresolved, ["Nowhere"]);
+        assert!(matches!(resolve_links_in(dir.path(), "../a"), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn backlinks_find_notes_linking_by_title_or_id() {
+        let dir = notes_folder(&[("a", "Plan", "[[ideas]]"), ("b", "Ideas", "[[Ideas]] links itself"), ("c", "Log", "[[b]]"), ("d", "Other", "[[Plan]]")]);
+        assert_eq!(ids(&linking_notes(dir.path(), "b").unwrap()), ["a", "c"]);
+        assert!(linking_notes(dir.path(), "c").unwrap().is_empty());
+    }
+
+    #[test]
+    fn renaming_rewrites_title_links_and_keeps_the_old_content() {
+        let dir = notes_folder(&[("a", "New", "# New"), ("b", "Log", "see [[old]] and [[a]]"), ("c", "Other", "no links")]);
+        let (count, changed) = rename_title_links_in(dir.path(), "a", "Old", "New", 5).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(ids(&changed), ["b"]);
+        assert_eq!(fs::read_to_string(note_file(dir.path(), "b")).unwrap(), "see [[New]] and [[a]]");
+        let history = dir.path().join(crate::versions::HISTORY_DIR).join("b");
+        let saved: Vec<String> = fs::read_dir(&history).unwrap().map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap()).collect();
+        assert_eq!(saved, ["see [[old]] and [[a]]"]);
+        assert!(!dir.path().join(crate::versions::HISTORY_DIR).join("c").exists());
+    }
+
+    #[test]
+    fn retarget_links_keeps_labels_and_ignores_case() {
+        let (content, count) = retarget_links("See [[Old]] and [[ old |the old one]].", "Old", "New");
+        assert_eq!(content, "See [[New]] and [[New|the old one]].");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn retarget_links_leaves_other_links_alone() {
+        let content = "[[Older]] [[Other|Old]] [[Old\nnote]] [[Old";
+        assert_eq!(retarget_links(content, "Old", "New"), (content.to_string(), 0));
+    }
+}
@@ -0,0 +1,67 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+pub(crate) fn parser_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+fn end_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Renders markdown as readable plain text: markup is dropped, links keep their
+/// text, and paragraphs and list items stay on their own lines.
+pub(crate) fn plain_text(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut list_depth = 0usize;
+    for event in Parser::new_ext(markdown, parser_options()) {
+        match event {
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => out.push('\n'),
+            Event::Start(Tag::List(_)) => {
+                end_line(&mut out);
+                list_depth += 1;
+            }
+            Event::End(TagEnd::List(_)) => {
+                list_depth = list_depth.saturating_sub(1);
+                if list_depth == 0 {
+                    out.push('\n');
+                }
+            }
+            Event::End(TagEnd::Paragraph) => {
+                end_line(&mut out);
+                // Inside a list, paragraphs belong to an item and shouldn't add blank lines.
+                if list_depth == 0 {
+                    out.push('\n');
+                }
+            }
+            Event::End(TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::BlockQuote(_) | TagEnd::Table) => {
+                end_line(&mut out);
+                out.push('\n');
+            }
+            Event::End(TagEnd::Item | TagEnd::TableHead | TagEnd::TableRow) => end_line(&mut out),
+            Event::End(TagEnd::TableCell) => out.push(' '),
+            _ => {}
+        }
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_drops_markup_but_keeps_the_text() {
+        let markdown = "# Title\n\nSome *emphasis*, **bold** and [a link](https://x.y).\n\n```rust\nfn main() {}\n```\n\n- one\n- two `code`\n\nEnd";
+        assert_eq!(plain_text(markdown), "Title\n\nSome emphasis, bold and a link.\n\nfn main() {}\n\none\ntwo code\n\nEnd");
+    }
+
+    #[test]
+    fn plain_text_joins_soft_breaks_and_keeps_hard_ones() {
+        assert_eq!(plain_text("one\ntwo  \nthree"), "one two\nthree");
+        assert_eq!(plain_text("## ~~Old~~ *new*"), "Old new");
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use fs2::FileExt;
+
+use crate::error::AppError;
+
+/// Advisory lock file claiming the notes folder for this process.
+const LOCK_FILE: &str = ".lock";
+
+/// The folder this process holds the lock for. The OS releases the lock when the
+/// file is closed, which also happens if the app exits or crashes.
+static HELD: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+/// Makes sure this process holds the lock on `dir`, taking it if needed. After the
+/// notes folder moved, the lock on the old folder is given up. Fails when another
+/// instance holds it, so two apps never write the same index.
+pub(crate) fn hold(dir: &Path) -> Result<(), AppError> {
+    let mut held = HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if held.as_ref().is_some_and(|(locked, _)| locked == dir) {
+        return Ok(());
+    }
+    *held = Some((dir.to_path_buf(), claim(dir)?));
+    Ok(())
+}
+
+/// Opens the lock file in `dir` and locks it. The lock lasts as long as the file stays open.
+fn claim(dir: &Path) -> Result<File, AppError> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(LOCK_FILE))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(file),
+        Err(err) if err.kind() == fs2::lock_contended_error().kind() => Err(AppError::Conflict(format!(
+            "The notes directory {} is locked by another instance",
+            dir.display()
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn a_second_claim_fails_until_the_first_is_released() {
+        let dir = TempDir::new();
+        let first = claim(dir.path()).unwrap();
+        assert!(matches!(claim(dir.path()), Err(AppError::Conflict(_))));
+        drop(first);
+        assert!(claim(dir.path()).is_ok());
+    }
+}
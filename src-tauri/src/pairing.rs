@@ -0,0 +1,115 @@
+use std::net::Ipv4Addr;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+
+use crate::{
+    error::AppError,
+    share::{device_id, device_name, local_ipv4s, start_send_all_notes_to, transfer_port, PeerInfo},
+};
+
+const PAIRING_PREFIX: &str = "qm1";
+/// Hex digits of the SHA-256 checksum appended to a code.
+const CHECKSUM_LEN: usize = 8;
+
+/// Everything a peer needs to reach this device without discovery.
+/// Short keys keep the QR code small.
+#[derive(Serialize, Deserialize)]
+struct PairingPayload {
+    n: String,
+    i: String,
+    p: u16,
+    d: String,
+}
+
+fn checksum(body: &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    digest.iter().take(CHECKSUM_LEN / 2).map(|b| format!("{:02x}", b)).collect()
+}
+
+fn encode_pairing(payload: &PairingPayload) -> Result<String, AppError> {
+    let body = URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?);
+    Ok(format!("{}.{}.{}", PAIRING_PREFIX, body, checksum(&body)))
+}
+
+fn decode_pairing(code: &str) -> Result<PeerInfo, AppError> {
+    let invalid = || AppError::InvalidInput("Invalid pairing code".into());
+    let mut parts = code.trim().split('.');
+    let (Some(prefix), Some(body), Some(sum), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    if prefix != PAIRING_PREFIX || sum != checksum(body) {
+        return Err(invalid());
+    }
+    let bytes = URL_SAFE_NO_PAD.decode(body).map_err(|_| invalid())?;
+    let payload: PairingPayload = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    let ip: Ipv4Addr = payload.i.parse().map_err(|_| invalid())?;
+    if payload.n.trim().is_empty() || payload.p == 0 || ip.is_unspecified() || ip.is_broadcast() {
+        return Err(invalid());
+    }
+    Ok(PeerInfo { name: payload.n, ip: ip.to_string(), port: payload.p, id: payload.d, note_count: None })
+}
+
+/// A compact string describing this device, for the frontend to show as a QR code.
+#[tauri::command]
+pub fn pairing_code(app: AppHandle) -> Result<String, AppError> {
+    let (ip, _) = local_ipv4s()
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::Network("No network connection to pair over".into()))?;
+    encode_pairing(&PairingPayload { n: device_name(&app), i: ip.to_string(), p: transfer_port(), d: device_id(&app) })
+}
+
+/// Decodes a scanned pairing code and starts sending all notes to that device.
+/// Progress arrives through the usual `share://send_status` events.
+#[tauri::command]
+pub fn connect_from_pairing(app: AppHandle, code: String) -> Result<PeerInfo, AppError> {
+    let peer = decode_pairing(&code)?;
+    start_send_all_notes_to(app, peer.ip.clone(), peer.port)?;
+    Ok(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> PairingPayload {
+        PairingPayload { n: "Desk".into(), i: "192.168.1.5".into(), p: 51516, d: "dev-1".into() }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let code = encode_pairing(&payload()).unwrap();
+        assert!(code.starts_with("qm1."));
+        let peer = decode_pairing(&format!("  {code}\n")).unwrap();
+        assert_eq!((peer.name.as_str(), peer.ip.as_str(), peer.port, peer.id.as_str()), ("Desk", "192.168.1.5", 51516, "dev-1"));
+    }
+
+    #[test]
+    fn rejects_tampered_or_truncated_codes() {
+        let code = encode_pairing(&payload()).unwrap();
+        let forged_body = URL_SAFE_NO_PAD.encode(br#"{"n":"Evil","i":"10.0.0.9","p":51516,"d":"x"}"#);
+        let (_, sum) = code.rsplit_once('.').unwrap();
+        let bad = [
+            code[..code.len() - 1].to_string(),
+            code[..code.len() / 2].to_string(),
+            format!("{PAIRING_PREFIX}.{forged_body}.{sum}"),
+            code.replacen("qm1", "qm2", 1),
+            format!("{code}.extra"),
+            String::new(),
+        ];
+        for code in bad {
+            assert!(matches!(decode_pairing(&code), Err(AppError::InvalidInput(_))), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_addresses() {
+        for (ip, port) in [("0.0.0.0", 51516), ("255.255.255.255", 51516), ("not-an-ip", 51516), ("192.168.1.5", 0)] {
+            let code = encode_pairing(&PairingPayload { i: ip.into(), p: port, ..payload() }).unwrap();
+            assert!(decode_pairing(&code).is_err(), "{ip}:{port}");
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::{fs, path::Path};
+
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{
+    build_summary_in, emit_note_changed, error::AppError, load_index, load_index_in, lock_index, note_file, note_path,
+    notes_dir, now_timestamp, read_note_lossy, save_index_in, settings::load_settings, versions, watcher,
+    StoredNoteMetadata,
+};
+
+/// Characters of context kept before the first content match in a snippet.
+const SNIPPET_BEFORE: usize = 40;
+const SNIPPET_AFTER: usize = 80;
+/// Title scores are multiplied by this so a title hit outranks a similar body hit.
+const TITLE_BOOST: i64 = 2;
+const DEFAULT_FUZZY_LIMIT: usize = 50;
+
+/// A match as UTF-16 offsets, the indices JavaScript strings use.
+#[derive(Serialize)]
+pub struct MatchRange {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    id: String,
+    title: String,
+    updated_at: String,
+    title_matches: Vec<MatchRange>,
+    /// Total matches in the note body.
+    match_count: usize,
+    /// Text around the first body match, with ranges relative to the snippet.
+    snippet: String,
+    snippet_matches: Vec<MatchRange>,
+}
+
+/// Compiles the query once for all notes. Literal queries are escaped, so both
+/// modes share one matcher and one notion of case sensitivity.
+pub(crate) fn build_matcher(query: &str, case_sensitive: bool, regex: bool) -> Result<Regex, AppError> {
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| AppError::InvalidInput(format!("Invalid regular expression: {e}")))
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn ranges(matcher: &Regex, text: &str) -> Vec<MatchRange> {
+    matcher
+        .find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            let start = utf16_len(&text[..m.start()]);
+            MatchRange { start, end: start + utf16_len(m.as_str()) }
+        })
+        .collect()
+}
+
+/// Byte index `chars` characters before/after `at`, clamped to the text.
+fn char_offset_back(text: &str, at: usize, chars: usize) -> usize {
+    text[..at].char_indices().rev().nth(chars.saturating_sub(1)).map_or(0, |(i, _)| i)
+}
+
+fn char_offset_forward(text: &str, at: usize, chars: usize) -> usize {
+    text[at..].char_indices().nth(chars).map_or(text.len(), |(i, _)| at + i)
+}
+
+fn snippet(matcher: &Regex, content: &str) -> (String, Vec<MatchRange>) {
+    let Some(first) = matcher.find_iter(content).find(|m| !m.is_empty()) else {
+        return (String::new(), Vec::new());
+    };
+    let start = char_offset_back(content, first.start(), SNIPPET_BEFORE);
+    let end = char_offset_forward(content, first.end(), SNIPPET_AFTER);
+    let text = content[start..end].replace('\n', " ");
+    let matches = ranges(matcher, &text);
+    (text, matches)
+}
+
+/// Searches note titles and bodies. `regex` treats the query as a regular
+/// expression; matching is case-insensitive unless `case_sensitive` is set.
+#[tauri::command]
+pub fn search_notes(
+    app: AppHandle,
+    query: String,
+    case_sensitive: Option<bool>,
+    regex: Option<bool>,
+) -> Result<Vec<SearchHit>, AppError> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let matcher = build_matcher(&query, case_sensitive.unwrap_or(false), regex.unwrap_or(false))?;
+
+    let mut hits = Vec::new();
+    for meta in load_index(&app)? {
+        let content = read_note_lossy(&note_path(&app, &meta.id)?).unwrap_or_default();
+        let title_matches = ranges(&matcher, &meta.title);
+        let match_count = matcher.find_iter(&content).filter(|m| !m.is_empty()).count();
+        if title_matches.is_empty() && match_count == 0 {
+            continue;
+        }
+        let (snippet, snippet_matches) = snippet(&matcher, &content);
+        hits.push(SearchHit {
+            id: meta.id,
+            title: meta.title,
+            updated_at: meta.updated_at,
+            title_matches,
+            match_count,
+            snippet,
+            snippet_matches,
+        });
+    }
+    Ok(hits)
+}
+
+#[derive(Serialize)]
+pub struct Replacement {
+    id: String,
+    title: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FuzzyHit {
+    id: String,
+    title: String,
+    updated_at: String,
+    /// Higher is more relevant; only meaningful relative to other hits.
+    score: i64,
+    title_matches: Vec<MatchRange>,
+    /// The best matching body line, with the matched characters as ranges.
+    snippet: String,
+    snippet_matches: Vec<MatchRange>,
+}
+
+/// Turns matched character indices into UTF-16 ranges, merging neighbours.
+fn char_ranges(text: &str, indices: &[usize]) -> Vec<MatchRange> {
+    let mut ranges: Vec<MatchRange> = Vec::new();
+    let mut offset = 0;
+    let mut wanted = indices.iter().peekable();
+    for (i, c) in text.chars().enumerate() {
+        if wanted.peek() == Some(&&i) {
+            wanted.next();
+            let end = offset + c.len_utf16();
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end = end,
+                _ => ranges.push(MatchRange { start: offset, end }),
+            }
+        }
+        offset += c.len_utf16();
+    }
+    ranges
+}
+
+/// Cuts a long line down to the context around its matched characters.
+fn fuzzy_snippet(line: &str, indices: &[usize]) -> (String, Vec<MatchRange>) {
+    let (Some(&first), Some(&last)) = (indices.first(), indices.last()) else {
+        return (String::new(), Vec::new());
+    };
+    let start = first.saturating_sub(SNIPPET_BEFORE);
+    let text: String = line.chars().skip(start).take(last + 1 + SNIPPET_AFTER - start).collect();
+    let shifted: Vec<usize> = indices.iter().map(|i| i - start).collect();
+    let matches = char_ranges(&text, &shifted);
+    (text.trim_end().to_string(), matches)
+}
+
+/// Finds notes even when the query has typos or skipped letters. Bodies are
+/// scored line by line and the best line becomes the snippet; title matches
+/// are boosted. Results are ordered by score, best first.
+#[tauri::command]
+pub fn fuzzy_search_notes(app: AppHandle, query: String, limit: Option<usize>) -> Result<Vec<FuzzyHit>, AppError> {
+    fuzzy_search_in(&notes_dir(&app)?, &query, limit.unwrap_or(DEFAULT_FUZZY_LIMIT))
+}
+
+fn fuzzy_search_in(dir: &Path, query: &str, limit: usize) -> Result<Vec<FuzzyHit>, AppError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let matcher = SkimMatcherV2::default().ignore_case();
+
+    let mut hits = Vec::new();
+    for meta in load_index_in(dir)? {
+        let content = read_note_lossy(&note_file(dir, &meta.id)).unwrap_or_default();
+        let title = matcher.fuzzy_indices(&meta.title, query);
+        let body = content
+            .lines()
+            .filter_map(|line| matcher.fuzzy_indices(line, query).map(|(score, indices)| (score, indices, line)))
+            .max_by_key(|(score, ..)| *score);
+
+        let title_score = title.as_ref().map(|(score, _)| score * TITLE_BOOST);
+        let body_score = body.as_ref().map(|(score, ..)| *score);
+        let Some(score) = title_score.max(body_score) else { continue };
+        let title_matches = title.map(|(_, indices)| char_ranges(&meta.title, &indices)).unwrap_or_default();
+        let (snippet, snippet_matches) =
+            body.map(|(_, indices, line)| fuzzy_snippet(line, &indices)).unwrap_or_default();
+        hits.push(FuzzyHit {
+            id: meta.id,
+            title: meta.title,
+            updated_at: meta.updated_at,
+            score,
+            title_matches,
+            snippet,
+            snippet_matches,
+        });
+    }
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+/// Replaces `find` in every note body and bumps the changed notes' `updated_at`.
+/// Each changed note's previous text is saved to its history first.
+/// Matching is case-sensitive. With `regex`, `replace` may refer to groups as `$1`;
+/// otherwise it is inserted literally. `preview` only counts, leaving files untouched.
+#[tauri::command]
+pub fn replace_across_notes(
+    app: AppHandle,
+    find: String,
+    replace: String,
+    regex: Option<bool>,
+    preview: Option<bool>,
+) -> Result<Vec<Replacement>, AppError> {
+    if find.is_empty() {
+        return Err(AppError::InvalidInput("Nothing to find".into()));
+    }
+    let regex = regex.unwrap_or(false);
+    let matcher = build_matcher(&find, true, regex)?;
+
+    let _guard = lock_index(&app)?;
+    let dir = notes_dir(&app)?;
+    let history_limit = load_settings(&app).note_history_limit;
+    let (replacements, changed) = replace_in(&dir, &matcher, &replace, regex, preview.unwrap_or(false), history_limit)?;
+    for meta in changed {
+        emit_note_changed(&app, &build_summary_in(&dir, meta));
+    }
+    Ok(replacements)
+}
+
+/// `replace_across_notes` for a known notes folder, returning the replacements
+/// and the entries of the notes that changed.
+fn replace_in(
+    dir: &Path,
+    matcher: &Regex,
+    replace: &str,
+    regex: bool,
+    dry_run: bool,
+    history_limit: usize,
+) -> Result<(Vec<Replacement>, Vec<StoredNoteMetadata>), AppError> {
+    let mut index = load_index_in(dir)?;
+    let mut replacements = Vec::new();
+    let now = now_timestamp();
+    for meta in index.iter_mut() {
+        let path = note_file(dir, &meta.id);
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let count = matcher.find_iter(&content).filter(|m| !m.is_empty()).count();
+        if count == 0 {
+            continue;
+        }
+        if !dry_run {
+            let updated = if regex {
+                matcher.replace_all(&content, replace)
+            } else {
+                matcher.replace_all(&content, NoExpand(replace))
+            };
+            if updated == content {
+                continue;
+            }
+            versions::snapshot_in(dir, &meta.id, history_limit)?;
+            watcher::mark_own_write(&meta.id);
+            fs::write(&path, updated.as_ref())?;
+            meta.updated_at = now.clone();
+        }
+        replacements.push(Replacement { id: meta.id.clone(), title: meta.title.clone(), count });
+    }
+
+    if dry_run || replacements.is_empty() {
+        return Ok((replacements, Vec::new()));
+    }
+    save_index_in(dir, &index)?;
+    let changed = index.into_iter().filter(|meta| replacements.iter().any(|r| r.id == meta.id)).collect();
+    Ok((replacements, changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(matcher: &Regex, text: &str) -> Vec<(usize, usize)> {
+        ranges(matcher, text).into_iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    #[test]
+    fn literal_queries_match_literally_and_respect_case() {
+        let sensitive = build_matcher("a.b", true, false).unwrap();
+        assert_eq!(spans(&sensitive, "axb a.b A.B"), [(4, 7)]);
+        let insensitive = build_matcher("a.b", false, false).unwrap();
+        assert_eq!(spans(&insensitive, "axb a.b A.B"), [(4, 7), (8, 11)]);
+    }
+
+    #[test]
+    fn regex_queries_support_alternation() {
+        let matcher = build_matcher("cat|dog", false, true).unwrap();
+        assert_eq!(spans(&matcher, "Dog and cat"), [(0, 3), (8, 11)]);
+    }
+
+    #[test]
+    fn ranges_are_utf16_offsets() {
+        let matcher = build_matcher("b", true, false).unwrap();
+        // "😀" is two UTF-16 units and "é" one, though they are 4 and 2 bytes.
+        assert_eq!(spans(&matcher, "😀é b"), [(4, 5)]);
+        let empty = build_matcher("x*", true, true).unwrap();
+        assert!(spans(&empty, "abc").is_empty());
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let err = build_matcher("(unclosed", false, true).unwrap_err();
+        assert!(matches!(&err, AppError::InvalidInput(m) if m.starts_with("Invalid regular expression")));
+        assert!(build_matcher("(unclosed", false, false).is_ok());
+    }
+
+    fn counts(replacements: &[Replacement]) -> Vec<(&str, usize)> {
+        replacements.iter().map(|r| (r.id.as_str(), r.count)).collect()
+    }
+
+    #[test]
+    fn literal_replace_rewrites_every_matching_note() {
+        let dir = crate::testing::notes_folder(&[("a", "A", "teh cat, teh dog"), ("b", "B", "teh end"), ("c", "C", "the end")]);
+        let matcher = build_matcher("teh", true, false).unwrap();
+        let (replacements, changed) = replace_in(dir.path(), &matcher, "$1the", false, false, 5).unwrap();
+        assert_eq!(counts(&replacements), [("a", 2), ("b", 1)]);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(fs::read_to_string(note_file(dir.path(), "a")).unwrap(), "$1the cat, $1the dog");
+        assert_eq!(fs::read_to_string(note_file(dir.path(), "c")).unwrap(), "the end");
+        let index = load_index_in(dir.path()).unwrap();
+        assert!(index[0].updated_at != "2024-01-01T00:00:00Z");
+        assert_eq!(index[2].updated_at, "2024-01-01T00:00:00Z");
+        assert!(dir.path().join(versions::HISTORY_DIR).join("a").is_dir());
+    }
+
+    #[test]
+    fn regex_replace_expands_groups() {
+        let dir = crate::testing::notes_folder(&[("a", "A", "2024-01-31")]);
+        let matcher = build_matcher(r"(\d+)-(\d+)-(\d+)", true, true).unwrap();
+        replace_in(dir.path(), &matcher, "$3/$2/$1", true, false, 5).unwrap();
+        assert_eq!(fs::read_to_string(note_file(dir.path(), "a")).unwrap(), "31/01/2024");
+    }
+
+    #[test]
+    fn preview_counts_without_touching_files() {
+        let dir = crate::testing::notes_folder(&[("a", "A", "teh teh")]);
+        let index_before = fs::read_to_string(dir.path().join("index.json")).unwrap();
+        let matcher = build_matcher("teh", true, false).unwrap();
+        let (replacements, changed) = replace_in(dir.path(), &matcher, "the", false, true, 5).unwrap();
+        assert_eq!(counts(&replacements), [("a", 2)]);
+        assert!(changed.is_empty());
+        assert_eq!(fs::read_to_string(note_file(dir.path(), "a")).unwrap(), "teh teh");
+        assert_eq!(fs::read_to_string(dir.path().join("index.json")).unwrap(), index_before);
+        assert!(!dir.path().join(versions::HISTORY_DIR).exists());
+    }
+
+    #[test]
+    fn fuzzy_title_matches_outrank_body_matches() {
+        let dir = crate::testing::notes_folder(&[
+            ("body", "Groceries", "remember the meeting notes"),
+            ("title", "Meeting", "agenda"),
+            ("none", "Other", "nothing relevant"),
+        ]);
+        let hits = fuzzy_search_in(dir.path(), "meeting", 10).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["title", "body"]);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!((hits[0].title_matches[0].start, hits[0].title_matches[0].end), (0, 7));
+        assert_eq!(hits[1].snippet, "remember the meeting notes");
+        assert_eq!((hits[1].snippet_matches[0].start, hits[1].snippet_matches[0].end), (13, 20));
+    }
+
+    #[test]
+    fn fuzzy_search_tolerates_skipped_letters() {
+        let dir = crate::testing::notes_folder(&[("a", "Quarterly report", ""), ("b", "Recipes", "")]);
+        let hits = fuzzy_search_in(dir.path(), "qrtrly", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, "a");
+        assert!(fuzzy_search_in(dir.path(), "  ", 10).unwrap().is_empty());
+        assert_eq!(fuzzy_search_in(dir.path(), "e", 1).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    error::AppError,
+    share::{DISCOVERY_PORT, TRANSFER_PORT},
+    PREVIEW_MAX_CHARS,
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+/// Where a settings file that can't be parsed is moved, so saving the defaults
+/// doesn't destroy what the user had.
+const CORRUPT_SETTINGS_FILE: &str = "settings.corrupt.json";
+
+/// A device saved for quick sending. The address is only where it was last seen.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FavoritePeer {
+    pub name: String,
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// How long an incoming offer may wait for the user before it's rejected.
+    pub(crate) pending_offer_timeout_secs: u64,
+    /// Offers announcing a larger archive are rejected before the user is asked.
+    pub(crate) max_transfer_bytes: u64,
+    /// Offers waiting for an answer at once; further ones are rejected until a slot frees up.
+    pub(crate) max_pending_offers: usize,
+    /// Name shown to peers during discovery; the OS hostname is used when unset.
+    pub(crate) device_name: Option<String>,
+    /// Stable id announced in discovery and transfer headers; generated on first use.
+    pub(crate) device_id: Option<String>,
+    /// Device ids the user has marked as trusted. Ids aren't secret, so a device
+    /// only counts as trusted while transfers are encrypted with `transfer_passphrase`.
+    pub(crate) trusted_peers: Vec<String>,
+    /// Accept offers from trusted devices without asking.
+    pub(crate) auto_accept_trusted: bool,
+    /// Refuse offers from devices that aren't trusted instead of asking the user.
+    /// Since trust needs encryption, this also refuses every unencrypted offer,
+    /// trusted id or not; set `transfer_passphrase` before turning it on.
+    pub(crate) reject_unknown_peers: bool,
+    /// When set, transfers are encrypted and both devices must use the same passphrase.
+    pub(crate) transfer_passphrase: Option<String>,
+    /// Local IPv4 address to discover and send from; every interface is used when unset.
+    pub(crate) source_ip: Option<String>,
+    /// Template used for new daily notes; a bare heading when unset.
+    pub(crate) daily_note_template: Option<String>,
+    /// Folder holding the notes, e.g. inside a synced drive; `notes/` in app data when unset.
+    pub(crate) notes_directory: Option<String>,
+    pub(crate) favorite_peers: Vec<FavoritePeer>,
+    /// Earlier versions kept per note; 0 turns version history off.
+    pub(crate) note_history_limit: usize,
+    /// Answer discovery pings while receiving. When off, only senders that already
+    /// know this device's address can reach it.
+    pub(crate) discoverable: bool,
+    /// Incoming and local edits this close together are treated as simultaneous
+    /// when merging, since the two devices' clocks may disagree by that much.
+    pub(crate) merge_clock_skew_secs: u64,
+    /// Preferred port for incoming transfers; a free one is used when it's taken.
+    pub(crate) transfer_port: u16,
+    /// Port discovery pings are sent to and answered on. Devices only find each
+    /// other when they use the same one.
+    pub(crate) discovery_port: u16,
+    /// Longest note preview, in bytes of text.
+    pub(crate) preview_length: usize,
+    /// Show previews as plain text instead of markdown.
+    pub(crate) strip_markdown_previews: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pending_offer_timeout_secs: 60,
+            max_transfer_bytes: 512 * 1024 * 1024,
+            max_pending_offers: 16,
+            device_name: None,
+            device_id: None,
+            trusted_peers: Vec::new(),
+            auto_accept_trusted: false,
+            reject_unknown_peers: false,
+            transfer_passphrase: None,
+            source_ip: None,
+            daily_note_template: None,
+            notes_directory: None,
+            favorite_peers: Vec::new(),
+            note_history_limit: 20,
+            discoverable: true,
+            merge_clock_skew_secs: 5,
+            transfer_port: TRANSFER_PORT,
+            discovery_port: DISCOVERY_PORT,
+            preview_length: PREVIEW_MAX_CHARS,
+            strip_markdown_previews: false,
+        }
+    }
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let base = app.path().app_data_dir()?;
+    fs::create_dir_all(&base)?;
+    Ok(base.join(SETTINGS_FILE))
+}
+
+pub(crate) fn load_settings(app: &AppHandle) -> Settings {
+    settings_path(app).map(|path| load_settings_from(&path)).unwrap_or_default()
+}
+
+/// A missing or unreadable settings file shouldn't break the app, so this falls back
+/// to defaults. One that can't be parsed is first moved to `CORRUPT_SETTINGS_FILE`.
+fn load_settings_from(path: &Path) -> Settings {
+    let Ok(data) = fs::read_to_string(path) else { return Settings::default() };
+    match serde_json::from_str(&data) {
+        Ok(settings) => settings,
+        Err(_) => {
+            let _ = fs::rename(path, path.with_file_name(CORRUPT_SETTINGS_FILE));
+            Settings::default()
+        }
+    }
+}
+
+pub(crate) fn save_settings(app: &AppHandle, settings: &Settings) -> Result<(), AppError> {
+    save_settings_to(&settings_path(app)?, settings)?;
+    crate::apply_preview_settings(settings);
+    Ok(())
+}
+
+fn save_settings_to(path: &Path, settings: &Settings) -> Result<(), AppError> {
+    let data = serde_json::to_string_pretty(settings)?;
+    fs::write(path, data).map_err(AppError::from)
+}
+
+/// All settings, for a settings screen. The transfer passphrase is never sent to
+/// the frontend; use `set_transfer_passphrase` to change it.
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> Settings {
+    Settings { transfer_passphrase: None, ..load_settings(&app) }
+}
+
+/// Replaces the stored settings. Fields missing from `settings` take their defaults.
+/// The device id, the passphrase and the notes folder are kept as they are: they
+/// have their own commands, which do more than store a value.
+#[tauri::command]
+pub fn update_settings(app: AppHandle, settings: Settings) -> Result<Settings, AppError> {
+    let current = load_settings(&app);
+    let updated = Settings {
+        device_id: current.device_id,
+        transfer_passphrase: current.transfer_passphrase,
+        notes_directory: current.notes_directory,
+        ..settings
+    };
+    save_settings(&app, &updated)?;
+    Ok(get_settings(app))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn a_missing_file_loads_the_defaults() {
+        let dir = TempDir::new();
+        let settings = load_settings_from(&dir.path().join(SETTINGS_FILE));
+        assert_eq!((settings.transfer_port, settings.discovery_port), (TRANSFER_PORT, DISCOVERY_PORT));
+        assert_eq!(settings.preview_length, PREVIEW_MAX_CHARS);
+        assert!(settings.discoverable && !settings.strip_markdown_previews);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let dir = TempDir::new();
+        let path = dir.path().join(SETTINGS_FILE);
+        let settings = Settings { device_name: Some("Desk".into()), transfer_port: 4000, preview_length: 80, strip_markdown_previews: true, ..Settings::default() };
+        save_settings_to(&path, &settings).unwrap();
+
+        let loaded = load_settings_from(&path);
+        assert_eq!(loaded.device_name.as_deref(), Some("Desk"));
+        assert_eq!((loaded.transfer_port, loaded.preview_length, loaded.strip_markdown_previews), (4000, 80, true));
+    }
+
+    #[test]
+    fn a_partial_file_fills_in_the_missing_fields() {
+        let dir = TempDir::new();
+        let path = dir.path().join(SETTINGS_FILE);
+        fs::write(&path, r#"{"deviceName":"Desk","noteHistoryLimit":3,"someFutureField":true}"#).unwrap();
+
+        let loaded = load_settings_from(&path);
+        assert_eq!(loaded.device_name.as_deref(), Some("Desk"));
+        assert_eq!(loaded.note_history_limit, 3);
+        assert_eq!(loaded.discovery_port, DISCOVERY_PORT);
+        assert_eq!(loaded.max_pending_offers, Settings::default().max_pending_offers);
+    }
+
+    #[test]
+    fn an_invalid_file_is_set_aside_before_the_defaults_are_used() {
+        let dir = TempDir::new();
+        let path = dir.path().join(SETTINGS_FILE);
+        fs::write(&path, r#"{"deviceName":"Desk","#).unwrap();
+
+        let loaded = load_settings_from(&path);
+        assert!(loaded.device_name.is_none());
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(dir.path().join(CORRUPT_SETTINGS_FILE)).unwrap(), r#"{"deviceName":"Desk","#);
+
+        save_settings_to(&path, &loaded).unwrap();
+        assert!(dir.path().join(CORRUPT_SETTINGS_FILE).exists());
+    }
+}
@@ -1,24 +1,33 @@
-use crate::{notes_dir, StoredNoteMetadata, preview_from_content};
+use crate::{attachments::{attachment_dir, attachment_files, ATTACHMENTS_DIR}, crypto::{decrypt_file, encrypt_file, EncryptionParams}, error::AppError, history::{record_transfer, TransferDirection, TransferRecord}, index_json, is_note_file, note_file, notes_dir, parse_index, parse_timestamp, settings::{load_settings, save_settings, FavoritePeer, Settings}, StoredNoteMetadata, preview_from_content};
 use serde::{Deserialize, Serialize};
 use std::{
     fs,
     io::{Read, Write},
-    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
-    path::Path,
-    time::Duration,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
-use time::macros::format_description;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering}};
 use uuid::Uuid;
-use if_addrs::{get_if_addrs, IfAddr};
+use if_addrs::{get_if_addrs, IfAddr, Ifv4Addr, Interface};
+use socket2::{Domain, Protocol, Socket, Type};
 
-const DISCOVERY_PORT: u16 = 51515;
-const TRANSFER_PORT: u16 = 51516;
+pub(crate) const DISCOVERY_PORT: u16 = 51515;
+pub(crate) const TRANSFER_PORT: u16 = 51516;
 const DISCOVERY_MAGIC: &str = "quickmark_discovery_v1";
 const TRANSFER_MAGIC: &str = "quickmark_transfer_v1";
+/// Read/write buffer for unchunked streams. Small buffers cost throughput on fast LANs.
+const TRANSFER_BUFFER_SIZE: usize = 64 * 1024;
+/// Chunked transfer mode: each chunk is length-prefixed and acknowledged before the next.
+const PROTOCOL_V2: &str = "v2";
+/// Largest chunk the protocol allows; receivers reject anything bigger.
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNK_ACK: &[u8; 1] = b"K";
+/// In chunked mode a peer that stops acknowledging is considered dead after this long.
+const CHUNK_ACK_TIMEOUT: Duration = Duration::from_secs(15);
 
 #[derive(Serialize, Deserialize)]
 struct DiscoveryPing {
@@ -27,14 +36,30 @@ struct DiscoveryPing {
     name: String,
     transfer_port: u16,
     id: String,
+    /// Only pongs carry it, and older peers omit it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note_count: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct PeerInfo {
     pub name: String,
     pub ip: String,
     pub port: u16,
     pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_count: Option<u32>,
+}
+
+/// What this device looks like to peers, for display on the share screen.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    pub name: String,
+    pub ips: Vec<String>,
+    pub discovery_port: u16,
+    pub transfer_port: u16,
 }
 
 fn host_name_fallback() -> String {
@@ -44,177 +69,890 @@ fn host_name_fallback() -> String {
         .unwrap_or_else(|| "QuickMark".to_string())
 }
 
-fn directed_broadcasts() -> Vec<SocketAddr> {
+/// The name peers see: the user's chosen name if set, otherwise the hostname.
+pub(crate) fn device_name(app: &AppHandle) -> String {
+    chosen_name(&load_settings(app)).unwrap_or_else(host_name_fallback)
+}
+
+/// The stored device name, trimmed; a blank name counts as unset.
+fn chosen_name(settings: &Settings) -> Option<String> {
+    settings.device_name.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string)
+}
+
+/// This device's id as seen by peers. It is persisted so trust decisions survive restarts.
+pub(crate) fn device_id(app: &AppHandle) -> String {
+    let mut settings = load_settings(app);
+    if let Some(id) = settings.device_id.clone() {
+        return id;
+    }
+    let id = Uuid::new_v4().to_string();
+    settings.device_id = Some(id.clone());
+    let _ = save_settings(app, &settings);
+    id
+}
+
+/// IPv4 interfaces a peer could reach. Loopback and link-local (169.254.x.x)
+/// addresses are left out: they only appear when there is no real network.
+fn reachable_ipv4s(ifaces: Vec<Interface>) -> impl Iterator<Item = (String, Ifv4Addr)> {
+    ifaces.into_iter().filter_map(|iface| match iface.addr {
+        IfAddr::V4(v4) if !v4.is_loopback() && !v4.ip.is_link_local() => Some((iface.name, v4)),
+        _ => None,
+    })
+}
+
+/// Reachable IPv4 addresses of this machine with their netmasks.
+pub(crate) fn local_ipv4s() -> Vec<(Ipv4Addr, Ipv4Addr)> {
+    reachable_ipv4s(get_if_addrs().unwrap_or_default()).map(|(_, v4)| (v4.ip, v4.netmask)).collect()
+}
+
+/// A network interface the user can pick as the source for sends.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkInterface {
+    pub name: String,
+    pub ip: String,
+    pub netmask: String,
+}
+
+#[tauri::command]
+pub fn list_interfaces() -> Result<Vec<NetworkInterface>, AppError> {
+    Ok(reachable_ipv4s(get_if_addrs()?)
+        .map(|(name, v4)| NetworkInterface { name, ip: v4.ip.to_string(), netmask: v4.netmask.to_string() })
+        .collect())
+}
+
+/// Chooses the local address sends go out from; `None` goes back to all interfaces.
+#[tauri::command]
+pub fn set_source_ip(app: AppHandle, ip: Option<String>) -> Result<(), AppError> {
+    let ip = chosen_source(ip.as_deref(), &local_ipv4s())?;
+    let mut settings = load_settings(&app);
+    settings.source_ip = ip;
+    save_settings(&app, &settings)
+}
+
+/// Validates a source address picked from `interfaces`; blank means none.
+fn chosen_source(ip: Option<&str>, interfaces: &[(Ipv4Addr, Ipv4Addr)]) -> Result<Option<String>, AppError> {
+    let Some(text) = ip.map(str::trim).filter(|s| !s.is_empty()) else { return Ok(None) };
+    let parsed: Ipv4Addr = text.parse()?;
+    if !interfaces.iter().any(|(local, _)| *local == parsed) {
+        return Err(AppError::InvalidInput(format!("{} is not an address of this device", parsed)));
+    }
+    Ok(Some(parsed.to_string()))
+}
+
+/// The configured source address, if it is still assigned to this machine.
+fn source_ip(app: &AppHandle) -> Option<Ipv4Addr> {
+    let ip: Ipv4Addr = load_settings(app).source_ip?.parse().ok()?;
+    local_ipv4s().iter().any(|(local, _)| *local == ip).then_some(ip)
+}
+
+/// UDP socket for sending discovery pings, bound to the chosen source address if any.
+fn discovery_socket(app: &AppHandle) -> Result<UdpSocket, AppError> {
+    let bind_ip = source_ip(app).unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let udp = UdpSocket::bind((bind_ip, 0)).map_err(AppError::network)?;
+    udp.set_broadcast(true).ok();
+    Ok(udp)
+}
+
+/// Broadcast addresses to ping on `port`. A socket bound to one address only pings that interface's subnet.
+fn directed_broadcasts(source: Option<Ipv4Addr>, port: u16) -> Vec<SocketAddr> {
+    broadcasts_for(&local_ipv4s(), source, port)
+}
+
+/// `directed_broadcasts` for a given set of `(address, netmask)` pairs.
+fn broadcasts_for(interfaces: &[(Ipv4Addr, Ipv4Addr)], source: Option<Ipv4Addr>, port: u16) -> Vec<SocketAddr> {
     let mut out = Vec::new();
-    if let Ok(ifaces) = get_if_addrs() {
-        for iface in ifaces {
-            if iface.is_loopback() { continue; }
-            if let IfAddr::V4(v4) = iface.addr {
-                let ip = v4.ip.octets();
-                let mask = v4.netmask.octets();
-                let bcast = [
-                    ip[0] | (!mask[0]),
-                    ip[1] | (!mask[1]),
-                    ip[2] | (!mask[2]),
-                    ip[3] | (!mask[3]),
-                ];
-                let addr = std::net::Ipv4Addr::from(bcast);
-                out.push(SocketAddr::from((addr, DISCOVERY_PORT)));
+    for &(ip, netmask) in interfaces {
+        if source.is_some_and(|src| src != ip) { continue; }
+        let ip = ip.octets();
+        let mask = netmask.octets();
+        let bcast = [
+            ip[0] | (!mask[0]),
+            ip[1] | (!mask[1]),
+            ip[2] | (!mask[2]),
+            ip[3] | (!mask[3]),
+        ];
+        let addr = Ipv4Addr::from(bcast);
+        out.push(SocketAddr::from((addr, port)));
+    }
+    // Always include global broadcast as last resort
+    out.push(SocketAddr::from((Ipv4Addr::BROADCAST, port)));
+    out
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryDiagnostics {
+    interfaces: Vec<NetworkInterface>,
+    source_ip: Option<String>,
+    /// Where a scan would send its pings, in order.
+    broadcasts: Vec<String>,
+    /// Whether a socket for sending pings could be opened; `bind_error` says why not.
+    socket_bound: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bind_error: Option<String>,
+    listener_running: bool,
+    /// Whether a running listener answers pings; see `set_discoverable`.
+    discoverable: bool,
+}
+
+/// What discovery would do right now, without sending anything. Meant for
+/// "no receiver found" reports.
+#[tauri::command]
+pub fn diagnose_discovery(app: AppHandle) -> Result<DiscoveryDiagnostics, AppError> {
+    let source = source_ip(&app);
+    let settings = load_settings(&app);
+    let bind_error = discovery_socket(&app).err().map(|e| e.to_string());
+    Ok(DiscoveryDiagnostics {
+        interfaces: list_interfaces()?,
+        source_ip: source.map(|ip| ip.to_string()),
+        broadcasts: directed_broadcasts(source, settings.discovery_port).iter().map(SocketAddr::to_string).collect(),
+        socket_bound: bind_error.is_none(),
+        bind_error,
+        listener_running: LISTENING.load(Ordering::SeqCst),
+        discoverable: settings.discoverable,
+    })
+}
+
+fn ping_bytes(name: &str, id: &str) -> Result<Vec<u8>, AppError> {
+    let ping = DiscoveryPing {
+        magic: DISCOVERY_MAGIC.to_string(),
+        kind: "ping".into(),
+        name: name.to_string(),
+        transfer_port: transfer_port(),
+        id: id.to_string(),
+        note_count: None,
+    };
+    Ok(serde_json::to_vec(&ping)?)
+}
+
+/// The reply to a ping, advertising the transfer `port` and how many notes this device holds.
+fn pong_bytes(name: &str, id: &str, port: u16, note_count: Option<u32>) -> Result<Vec<u8>, AppError> {
+    let pong = DiscoveryPing {
+        magic: DISCOVERY_MAGIC.to_string(),
+        kind: "pong".into(),
+        name: name.to_string(),
+        transfer_port: port,
+        id: id.to_string(),
+        note_count,
+    };
+    Ok(serde_json::to_vec(&pong)?)
+}
+
+/// Entries in the index of `dir`; `None` when it can't be read.
+fn note_count(dir: &Path) -> Option<u32> {
+    load_index_from(dir).ok().map(|index| index.len() as u32)
+}
+
+fn broadcast_ping(udp: &UdpSocket, name: &str, id: &str, port: u16) -> Result<(), AppError> {
+    let bytes = ping_bytes(name, id)?;
+    let source = match udp.local_addr() {
+        Ok(SocketAddr::V4(addr)) if !addr.ip().is_unspecified() => Some(*addr.ip()),
+        _ => None,
+    };
+    for addr in directed_broadcasts(source, port) { let _ = udp.send_to(&bytes, addr); }
+    Ok(())
+}
+
+/// Characters some filesystem can't store in a file name. They are escaped as
+/// `%XX` in zip entry names, together with `%` itself, so an archive extracts to
+/// the same files on every platform. Spaces and non-ASCII text are left as-is;
+/// zip entry names are UTF-8.
+fn needs_escape(c: char) -> bool {
+    c.is_control() || matches!(c, '%' | '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+}
+
+/// Builds a zip entry name from path components, escaping each one so a
+/// component can never introduce another level of folders.
+fn entry_name(components: &[&str]) -> String {
+    let escape = |component: &str| {
+        let mut out = String::with_capacity(component.len());
+        for c in component.chars() {
+            if needs_escape(c) {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).bytes() { out.push_str(&format!("%{:02X}", b)); }
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    };
+    components.iter().map(|c| escape(c)).collect::<Vec<_>>().join("/")
+}
+
+/// Reverses `entry_name`, returning the path relative to the extraction folder.
+/// Entries that could land outside it (`..`, absolute paths, empty components)
+/// give `None`. Names from older versions contain no escapes and pass through.
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.trim_end_matches('/').split('/') {
+        let mut bytes = Vec::with_capacity(component.len());
+        let mut rest = component.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+            match hex {
+                Some(decoded) if b == b'%' => { bytes.push(decoded); rest = &tail[2..]; }
+                _ => { bytes.push(b); rest = tail; }
             }
         }
+        let component = String::from_utf8(bytes).ok()?;
+        if component.is_empty() || component == "." || component == ".." || component.contains(['/', '\\']) { return None; }
+        path.push(component);
     }
-    // Always include global broadcast as last resort
-    match format!("255.255.255.255:{}", DISCOVERY_PORT).parse::<SocketAddr>() {
-        Ok(a) => out.push(a),
-        Err(_) => {}
+    (!path.has_root()).then_some(path)
+}
+
+/// Adds a note's attachments under the same relative paths they have in the notes folder.
+fn zip_attachments(zip: &mut zip::ZipWriter<fs::File>, dir: &Path, note_id: &str, options: zip::write::FileOptions) -> Result<(), AppError> {
+    for (path, _) in attachment_files(dir, note_id) {
+        let Some(file_name) = path.file_name().and_then(|s| s.to_str()) else { continue };
+        zip.start_file(entry_name(&[ATTACHMENTS_DIR, note_id, file_name]), options)?;
+        zip.write_all(&fs::read(&path)?)?;
+    }
+    Ok(())
+}
+
+/// Scratch location for transfer archives and extraction. It is outside the notes
+/// folder so nothing in flight can end up inside an archive or wake the file watcher.
+fn scratch_path(app: &AppHandle, name: &str) -> Result<PathBuf, AppError> {
+    let dir = scratch_dir(app)?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}-{}", Uuid::new_v4(), name)))
+}
+
+/// Free bytes on the fuller of the volumes a transfer writes to: the scratch
+/// folder for the archive and its extraction, the notes folder for the result.
+/// `None` when it can't be determined.
+fn free_space(app: &AppHandle) -> Option<u64> {
+    let scratch = scratch_dir(app).ok()?;
+    fs::create_dir_all(&scratch).ok()?;
+    let notes = notes_dir(app).ok()?;
+    Some(fs2::available_space(scratch).ok()?.min(fs2::available_space(notes).ok()?))
+}
+
+/// Whether `free` bytes can hold a transfer of `size`: the archive plus its
+/// extracted copy, so roughly twice the size. Unknown free space doesn't block a transfer.
+fn has_room(free: Option<u64>, size: u64) -> bool {
+    free.is_none_or(|free| free >= size.saturating_mul(2))
+}
+
+/// Removes a scratch file or folder when dropped, so every early return cleans up.
+struct ScratchGuard(PathBuf);
+
+impl Drop for ScratchGuard {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = fs::remove_dir_all(&self.0);
+        } else {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+}
+
+/// Inside this app's data folder rather than the shared temp folder, so other
+/// users and other apps can't reach it.
+fn scratch_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    Ok(app.path().app_data_dir()?.join("scratch"))
+}
+
+/// Names older versions used for transfer scratch files inside the notes folder.
+const LEGACY_SCRATCH: &[&str] = &[
+    "incoming_notes.zip",
+    "incoming_notes.zip.enc",
+    "incoming_tmp",
+    "outgoing_notes.zip",
+    "outgoing_notes.zip.enc",
+    "outgoing_single.zip",
+    "outgoing_single.zip.enc",
+    "outgoing_selected.zip",
+    "outgoing_selected.zip.enc",
+];
+
+/// Removes archives and extraction folders left behind by a transfer that never
+/// finished, e.g. because the app crashed. Runs once at startup, before any
+/// transfer can be in flight, and only once this instance holds the notes lock:
+/// a second instance must not delete the first one's transfers. Only the fixed
+/// scratch names are touched, never notes.
+pub(crate) fn clean_transfer_leftovers(app: &AppHandle) {
+    if let Ok(dir) = scratch_dir(app) {
+        let _ = fs::remove_dir_all(dir);
+    }
+    if let Ok(dir) = notes_dir(app) {
+        remove_legacy_scratch(&dir);
+    }
+}
+
+fn remove_legacy_scratch(dir: &Path) {
+    for name in LEGACY_SCRATCH {
+        let path = dir.join(name);
+        if path.is_dir() {
+            let _ = fs::remove_dir_all(&path);
+        } else if path.is_file() {
+            let _ = fs::remove_file(&path);
+        }
     }
-    out
 }
 
-fn zip_notes_dir(dir: &Path, out_path: &Path) -> Result<(), String> {
-    let file = fs::File::create(out_path).map_err(|e| e.to_string())?;
+/// Archives the index, every note file and their attachments, returning how many notes were included.
+/// Archives always carry the index as a bare array, which every version can read.
+fn zip_notes_dir(dir: &Path, out_path: &Path) -> Result<ArchivedNotes, AppError> {
+    // A note file that isn't valid UTF-8 (e.g. damaged by a sync tool) would fail
+    // every reader on the other side, so it is left out along with its index entry.
+    let mut note_files = Vec::new();
+    let mut skipped = Vec::new();
+    let mut skipped_ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !is_note_file(&path) { continue; }
+        let (Some(name), Some(id)) = (path.file_name().and_then(|s| s.to_str()), path.file_stem().and_then(|s| s.to_str())) else { continue };
+        let (name, id) = (name.to_string(), id.to_string());
+        if std::str::from_utf8(&fs::read(&path)?).is_err() {
+            skipped.push(name);
+            skipped_ids.push(id);
+            continue;
+        }
+        note_files.push((path, name, id));
+    }
+
+    let file = fs::File::create(out_path)?;
     let mut zip = zip::ZipWriter::new(file);
     let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
-    let mut add_file = |p: &Path, name_in_zip: &str| -> Result<(), String> {
-        zip.start_file(name_in_zip, options).map_err(|e| e.to_string())?;
-        let mut f = fs::File::open(p).map_err(|e| e.to_string())?;
+    let mut index = load_index_from(dir)?;
+    index.retain(|meta| !skipped_ids.contains(&meta.id));
+    zip.start_file("index.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+
+    let mut add_file = |p: &Path, name_in_zip: &str| -> Result<(), AppError> {
+        zip.start_file(name_in_zip, options)?;
+        let mut f = fs::File::open(p)?;
         let mut buf = Vec::new();
-        f.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-        zip.write_all(&buf).map_err(|e| e.to_string())?;
+        f.read_to_end(&mut buf)?;
+        zip.write_all(&buf)?;
         Ok(())
     };
 
-    // index.json + all .md files
-    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if path.is_file() {
-            let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-            if name.eq_ignore_ascii_case("index.json") || name.ends_with(".md") {
-                add_file(&path, name)?;
-            }
-        }
+    let mut note_ids = Vec::new();
+    for (path, name, id) in &note_files {
+        add_file(path, &entry_name(&[name]))?;
+        note_ids.push(id.clone());
+    }
+    let notes = note_ids.len() as u32;
+    for id in &note_ids {
+        zip_attachments(&mut zip, dir, id, options)?;
     }
 
-    zip.finish().map_err(|e| e.to_string())?;
-    Ok(())
+    zip.finish()?;
+    Ok(ArchivedNotes { notes, skipped })
+}
+
+struct ArchivedNotes {
+    notes: u32,
+    /// Note files left out because they aren't valid UTF-8.
+    skipped: Vec<String>,
 }
 
-fn unzip_into(dir: &Path, zip_path: &Path) -> Result<(), String> {
-    let file = fs::File::open(zip_path).map_err(|e| e.to_string())?;
-    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+fn unzip_into(dir: &Path, zip_path: &Path) -> Result<(), AppError> {
+    let file = fs::File::open(zip_path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
     for i in 0..archive.len() {
-        let mut f = archive.by_index(i).map_err(|e| e.to_string())?;
-        let out = dir.join(f.name());
+        let mut f = archive.by_index(i)?;
+        let Some(relative) = entry_path(f.name()) else { continue };
+        let out = dir.join(relative);
         if f.is_dir() {
-            fs::create_dir_all(&out).map_err(|e| e.to_string())?;
+            fs::create_dir_all(&out)?;
         } else {
             if let Some(parent) = out.parent() {
-                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                fs::create_dir_all(parent)?;
             }
-            let mut outfile = fs::File::create(&out).map_err(|e| e.to_string())?;
-            std::io::copy(&mut f, &mut outfile).map_err(|e| e.to_string())?;
+            let mut outfile = fs::File::create(&out)?;
+            std::io::copy(&mut f, &mut outfile)?;
         }
     }
     Ok(())
 }
 
-fn load_index_from(dir: &Path) -> Result<Vec<StoredNoteMetadata>, String> {
+/// Metadata for every note file in `dir`, for archives that arrive without an
+/// index. Titles come from the content and timestamps from the file.
+fn index_from_files(dir: &Path) -> Result<Vec<StoredNoteMetadata>, AppError> {
+    let mut index = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !is_note_file(&path) { continue; }
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else { continue };
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let updated_at = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| time::OffsetDateTime::from(t).format(&time::format_description::well_known::Rfc3339).ok())
+            .unwrap_or_else(crate::now_timestamp);
+        let extension = path.extension().and_then(|s| s.to_str()).filter(|ext| *ext != "md").map(str::to_string);
+        index.push(StoredNoteMetadata {
+            id,
+            title: crate::derive_title(&content),
+            updated_at,
+            created_at: None,
+            color: None,
+            tags: Vec::new(),
+            archived: false,
+            extension,
+        });
+    }
+    Ok(index)
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string()
+}
+
+fn load_index_from(dir: &Path) -> Result<Vec<StoredNoteMetadata>, AppError> {
     let p = dir.join("index.json");
     if !p.exists() { return Ok(Vec::new()); }
-    let s = fs::read_to_string(p).map_err(|e| e.to_string())?;
-    let items: Vec<StoredNoteMetadata> = serde_json::from_str(&s).map_err(|e| e.to_string())?;
-    Ok(items)
+    let s = fs::read_to_string(p)?;
+    Ok(parse_index(&s)?.0)
 }
 
-fn zip_single_note(dir: &Path, note_id: &str, out_path: &Path) -> Result<(), String> {
+fn zip_single_note(dir: &Path, note_id: &str, out_path: &Path) -> Result<(), AppError> {
     let all = load_index_from(dir)?;
     let meta = all.into_iter().find(|m| m.id == note_id)
-        .ok_or_else(|| "Note metadata not found".to_string())?;
-    let md_path = dir.join(format!("{}.md", note_id));
-    if !md_path.exists() { return Err("Note file not found".into()); }
+        .ok_or_else(|| AppError::NotFound("Note metadata not found".into()))?;
+    let md_path = note_file(dir, note_id);
+    if !md_path.exists() { return Err(AppError::NotFound("Note file not found".into())); }
 
-    let file = fs::File::create(out_path).map_err(|e| e.to_string())?;
+    let file = fs::File::create(out_path)?;
     let mut zip = zip::ZipWriter::new(file);
     let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
     // index.json with single entry
-    let idx_json = serde_json::to_string_pretty(&vec![meta]).map_err(|e| e.to_string())?;
-    zip.start_file("index.json", options).map_err(|e| e.to_string())?;
-    zip.write_all(idx_json.as_bytes()).map_err(|e| e.to_string())?;
+    let idx_json = serde_json::to_string_pretty(&vec![meta])?;
+    zip.start_file("index.json", options)?;
+    zip.write_all(idx_json.as_bytes())?;
 
     // the .md file
-    zip.start_file(format!("{}.md", note_id), options).map_err(|e| e.to_string())?;
-    let mut f = fs::File::open(md_path).map_err(|e| e.to_string())?;
+    zip.start_file(entry_name(&[&file_name_of(&md_path)]), options)?;
+    let mut f = fs::File::open(md_path)?;
     let mut buf = Vec::new();
-    f.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-    zip.write_all(&buf).map_err(|e| e.to_string())?;
+    f.read_to_end(&mut buf)?;
+    zip.write_all(&buf)?;
+    zip_attachments(&mut zip, dir, note_id, options)?;
 
-    zip.finish().map_err(|e| e.to_string())?;
+    zip.finish()?;
     Ok(())
 }
 
-fn merge_index(dest_index_path: &Path, incoming_index: &[StoredNoteMetadata]) -> Result<(), String> {
+/// Archives the given notes; ids without a note file are skipped, but at least one must resolve.
+/// Returns how many notes were included.
+fn zip_selected_notes(dir: &Path, ids: &[String], out_path: &Path) -> Result<u32, AppError> {
+    let selected: Vec<StoredNoteMetadata> = load_index_from(dir)?
+        .into_iter()
+        .filter(|m| ids.contains(&m.id) && note_file(dir, &m.id).exists())
+        .collect();
+    if selected.is_empty() { return Err(AppError::NotFound("None of the selected notes were found".into())); }
+
+    let file = fs::File::create(out_path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let idx_json = serde_json::to_string_pretty(&selected)?;
+    zip.start_file("index.json", options)?;
+    zip.write_all(idx_json.as_bytes())?;
+
+    for meta in &selected {
+        let md_path = note_file(dir, &meta.id);
+        zip.start_file(entry_name(&[&file_name_of(&md_path)]), options)?;
+        let buf = fs::read(&md_path)?;
+        zip.write_all(&buf)?;
+        zip_attachments(&mut zip, dir, &meta.id, options)?;
+    }
+
+    zip.finish()?;
+    Ok(selected.len() as u32)
+}
+
+/// How to resolve an incoming note whose id already exists locally.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Keep whichever copy has the newer `updated_at`.
+    #[default]
+    Newest,
+    /// When neither copy is newer, keep the local one and store a differing
+    /// incoming copy under a new id. A strictly newer copy still wins.
+    KeepBoth,
+}
+
+/// What an import does with the notes already on this device.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Combine both sets according to the `MergeStrategy`.
+    #[default]
+    Merge,
+    /// Make the received notes the whole library, e.g. when setting up a new
+    /// device. The previous notes are moved to `backups/`, not deleted.
+    Replace,
+}
+
+/// Where `ImportMode::Replace` moves the notes it replaces, one folder per import.
+pub(crate) const BACKUPS_DIR: &str = "backups";
+
+/// Which incoming notes were new to this device and which replaced an older local copy.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeOutcome {
+    added: Vec<String>,
+    updated: Vec<String>,
+    /// Ids of incoming notes that were stored as copies alongside the local version.
+    kept_both: Vec<String>,
+    /// Every note in the incoming index, including ones that changed nothing.
+    received: usize,
+    /// Incoming notes that weren't newer than the local copy, or had an unusable id.
+    skipped: usize,
+}
+
+impl MergeOutcome {
+    fn stats(&self) -> serde_json::Value {
+        serde_json::json!({
+            "received": self.received,
+            "added": self.added.len(),
+            "updated": self.updated.len(),
+            "skipped": self.skipped,
+            "conflicted": self.kept_both.len(),
+        })
+    }
+
+    /// "Received 10 notes: 3 new, 2 updated, 5 unchanged", leaving out empty parts.
+    fn summary(&self) -> String {
+        if self.received == 0 {
+            return "Nothing to import".into();
+        }
+        let noun = if self.received == 1 { "note" } else { "notes" };
+        let parts: Vec<String> = [
+            (self.added.len(), "new"),
+            (self.updated.len(), "updated"),
+            (self.kept_both.len(), "kept as copies"),
+            (self.skipped, "unchanged"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{count} {label}"))
+        .collect();
+        if parts.is_empty() {
+            format!("Received {} {}", self.received, noun)
+        } else {
+            format!("Received {} {}: {}", self.received, noun, parts.join(", "))
+        }
+    }
+}
+
+/// Whether two timestamps are at most `skew` apart. Unparseable ones never are.
+fn within(a: &str, b: &str, skew: Duration) -> bool {
+    match (parse_timestamp(a), parse_timestamp(b)) {
+        (Some(a), Some(b)) => (a - b).unsigned_abs() <= skew,
+        _ => false,
+    }
+}
+
+/// Moves the current index, note files, attachments and history into a new
+/// `backups/` folder and installs the incoming index in their place. The caller
+/// copies the note files, as after a merge. An archive without a single usable
+/// note is refused, so a bad transfer can't empty the library.
+fn replace_index(dest_dir: &Path, incoming_dir: &Path, incoming_index: &[StoredNoteMetadata]) -> Result<MergeOutcome, AppError> {
+    let kept: Vec<StoredNoteMetadata> = incoming_index
+        .iter()
+        .filter(|m| !m.id.is_empty() && !m.id.starts_with('.') && !m.id.contains(['/', '\\']) && note_file(incoming_dir, &m.id).exists())
+        .cloned()
+        .collect();
+    if kept.is_empty() {
+        return Err(AppError::InvalidInput("The archive contains no notes; your notes were left as they are".into()));
+    }
+
+    let stamp = time::OffsetDateTime::now_utc()
+        .format(time::macros::format_description!("[year][month][day]T[hour][minute][second]Z"))
+        .unwrap_or_default();
+    let backup = dest_dir.join(BACKUPS_DIR).join(format!("{}-{}", stamp, &Uuid::new_v4().to_string()[..8]));
+    fs::create_dir_all(&backup)?;
+    for entry in fs::read_dir(dest_dir)? {
+        let path = entry?.path();
+        let name = file_name_of(&path);
+        if name == "index.json" || is_note_file(&path) || name == ATTACHMENTS_DIR || name == crate::versions::HISTORY_DIR {
+            fs::rename(&path, backup.join(&name))?;
+        }
+    }
+
+    fs::write(dest_dir.join("index.json"), index_json(&kept)?)?;
+    crate::tags::invalidate_tag_counts();
+    Ok(MergeOutcome {
+        received: incoming_index.len(),
+        skipped: incoming_index.len() - kept.len(),
+        added: kept.into_iter().map(|m| m.id).collect(),
+        ..Default::default()
+    })
+}
+
+/// A note was created once, so the earlier of two creation times is the real one.
+/// Either side may come from a version that didn't record it.
+fn earliest_created(a: Option<String>, b: Option<String>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if is_newer(&a, &b) { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn same_note_content(a: &Path, b: &Path) -> bool {
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Compares timestamps by the instant they name rather than as strings, since
+/// notes from other devices may use a different format. Unparseable ones lose.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    match (parse_timestamp(candidate), parse_timestamp(current)) {
+        (Some(a), Some(b)) => a > b,
+        (Some(_), None) => true,
+        (None, Some(_)) => false,
+        (None, None) => candidate > current,
+    }
+}
+
+/// `skew` is how far apart two devices' clocks may be. Edits that close together
+/// can't be ordered by timestamp: identical content is left alone, and differing
+/// content is kept as a copy, whatever the strategy.
+fn merge_index(dest_dir: &Path, incoming_dir: &Path, incoming_index: &[StoredNoteMetadata], strategy: MergeStrategy, skew: Duration, peer_label: &str) -> Result<MergeOutcome, AppError> {
+    let dest_index_path = dest_dir.join("index.json");
     let mut current: Vec<StoredNoteMetadata> = if dest_index_path.exists() {
-        let s = fs::read_to_string(dest_index_path).map_err(|e| e.to_string())?;
-        serde_json::from_str(&s).map_err(|e| e.to_string())?
+        let s = fs::read_to_string(&dest_index_path)?;
+        parse_index(&s)?.0
     } else {
         Vec::new()
     };
 
+    let mut outcome = MergeOutcome { received: incoming_index.len(), ..Default::default() };
     for incoming in incoming_index {
+        // Ids become file names; an archive must not be able to point outside the notes folder.
+        if incoming.id.is_empty() || incoming.id.starts_with('.') || incoming.id.contains(['/', '\\']) {
+            outcome.skipped += 1;
+            continue;
+        }
         match current.iter_mut().find(|m| m.id == incoming.id) {
             Some(existing) => {
-                // Prefer the newer updated_at
-                if incoming.updated_at > existing.updated_at {
+                let local_md = note_file(dest_dir, &incoming.id);
+                let incoming_md = note_file(incoming_dir, &incoming.id);
+                let both_exist = local_md.exists() && incoming_md.exists();
+                let too_close = both_exist && within(&incoming.updated_at, &existing.updated_at, skew);
+                let tied = !is_newer(&incoming.updated_at, &existing.updated_at) && !is_newer(&existing.updated_at, &incoming.updated_at);
+                let keep_both = too_close || (strategy == MergeStrategy::KeepBoth && tied);
+                if keep_both && both_exist && !same_note_content(&local_md, &incoming_md) {
+                    let copy_id = Uuid::new_v4().to_string();
+                    let mut copy = incoming.clone();
+                    copy.id = copy_id.clone();
+                    // The copy gets its own attachments, with links pointing at them.
+                    let content = fs::read_to_string(&incoming_md)?.replace(
+                        &format!("{ATTACHMENTS_DIR}/{}/", incoming.id),
+                        &format!("{ATTACHMENTS_DIR}/{copy_id}/"),
+                    );
+                    fs::write(dest_dir.join(file_name_of(&incoming_md).replacen(&incoming.id, &copy_id, 1)), content)?;
+                    for (file, _) in attachment_files(incoming_dir, &incoming.id) {
+                        let copy_dir = attachment_dir(dest_dir, &copy_id);
+                        fs::create_dir_all(&copy_dir)?;
+                        if let Some(name) = file.file_name() { fs::copy(&file, copy_dir.join(name))?; }
+                    }
+                    copy.title = format!("{} (from {})", incoming.title, peer_label);
+                    current.push(copy);
+                    outcome.kept_both.push(copy_id);
+                } else if !too_close && is_newer(&incoming.updated_at, &existing.updated_at) {
+                    // Prefer the newer updated_at; the whole entry travels, tags and color included.
+                    let created_at = earliest_created(existing.created_at.take(), incoming.created_at.clone());
                     *existing = incoming.clone();
+                    existing.created_at = created_at;
+                    outcome.updated.push(incoming.id.clone());
+                } else {
+                    // The local entry wins, but a creation time only the sender knew is still worth keeping.
+                    existing.created_at = earliest_created(existing.created_at.take(), incoming.created_at.clone());
+                    outcome.skipped += 1;
                 }
             }
-            None => current.push(incoming.clone()),
+            None => {
+                current.push(incoming.clone());
+                outcome.added.push(incoming.id.clone());
+            }
         }
     }
 
-    let data = serde_json::to_string_pretty(&current).map_err(|e| e.to_string())?;
-    fs::write(dest_index_path, data).map_err(|e| e.to_string())
+    fs::write(&dest_index_path, index_json(&current)?)?;
+    crate::tags::invalidate_tag_counts();
+    Ok(outcome)
 }
 
-fn read_u64_be(stream: &mut TcpStream) -> Result<u64, String> {
+fn read_u64_be(stream: &mut TcpStream) -> Result<u64, AppError> {
     let mut buf = [0u8; 8];
-    stream.read_exact(&mut buf).map_err(|e| e.to_string())?;
+    stream.read_exact(&mut buf).map_err(AppError::network)?;
     Ok(u64::from_be_bytes(buf))
 }
 
-fn write_u64_be(stream: &mut TcpStream, val: u64) -> Result<(), String> {
-    stream.write_all(&val.to_be_bytes()).map_err(|e| e.to_string())
+fn write_u64_be(stream: &mut TcpStream, val: u64) -> Result<(), AppError> {
+    stream.write_all(&val.to_be_bytes()).map_err(AppError::network)
+}
+
+/// Speed is averaged over this much recent progress so it doesn't jump with every chunk.
+const RATE_WINDOW: Duration = Duration::from_secs(3);
+
+/// Tracks transfer progress to report speed and time remaining.
+struct RateMeter {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateMeter {
+    fn new() -> Self {
+        Self { samples: VecDeque::from([(Instant::now(), 0)]) }
+    }
+
+    /// Records `done` of `total` bytes and returns `(bytes per second, seconds left)`.
+    /// The time left is unknown until some data has moved.
+    fn record(&mut self, done: u64, total: u64) -> (u64, Option<u64>) {
+        let now = Instant::now();
+        self.samples.push_back((now, done));
+        // Keep one sample older than the window as the baseline for the average.
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) > RATE_WINDOW {
+            self.samples.pop_front();
+        }
+        let (since, from) = self.samples[0];
+        let rate = bytes_per_sec(done.saturating_sub(from), now.duration_since(since));
+        let eta = (rate > 0.0).then(|| (total.saturating_sub(done) as f64 / rate).ceil() as u64);
+        (rate.round() as u64, eta)
+    }
+}
+
+/// A receive that produced no event for this long gets a heartbeat.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// While alive, emits `share://recv_status` `{"phase":"receiving","heartbeat":true}`
+/// whenever no other event for the transfer went out for `HEARTBEAT_INTERVAL`, so
+/// the UI can tell a slow sender from a dead one. Stops when dropped.
+struct Heartbeat {
+    stop: Arc<AtomicBool>,
+    last_event: Arc<Mutex<Instant>>,
+}
+
+impl Heartbeat {
+    fn start(app: AppHandle, id: String) -> Self {
+        Self::every(HEARTBEAT_INTERVAL, move || {
+            let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"receiving","id":id,"heartbeat":true}));
+        })
+    }
+
+    /// Calls `beat` whenever `interval` passed without a beat or a `touch`.
+    fn every(interval: Duration, mut beat: impl FnMut() + Send + 'static) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let last_event = Arc::new(Mutex::new(Instant::now()));
+        let (thread_stop, thread_last) = (stop.clone(), last_event.clone());
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval / 4);
+            if thread_stop.load(Ordering::SeqCst) {
+                break;
+            }
+            let mut last = thread_last.lock().unwrap();
+            if last.elapsed() >= interval {
+                beat();
+                *last = Instant::now();
+            }
+        });
+        Self { stop, last_event }
+    }
+
+    /// Called when a progress event went out, which counts as a sign of life.
+    fn touch(&self) {
+        *self.last_event.lock().unwrap() = Instant::now();
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Zero rather than infinite or NaN when no time has passed.
+fn bytes_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { bytes as f64 / secs } else { 0.0 }
 }
 
-fn send_file(stream: &mut TcpStream, file_path: &Path) -> Result<(), String> {
-    let mut f = fs::File::open(file_path).map_err(|e| e.to_string())?;
-    let size = f.metadata().map_err(|e| e.to_string())?.len();
+/// Sends a length-prefixed file, reporting `(sent, total)` after every write. In chunked
+/// mode every chunk carries its own length and waits for the receiver's ACK.
+fn send_file(stream: &mut TcpStream, file_path: &Path, chunked: bool, on_progress: &mut dyn FnMut(u64, u64)) -> Result<(), AppError> {
+    let mut f = fs::File::open(file_path)?;
+    let size = f.metadata()?.len();
     write_u64_be(stream, size)?;
-    let mut buf = [0u8; 8192];
+    if chunked {
+        stream.set_read_timeout(Some(CHUNK_ACK_TIMEOUT)).ok();
+    }
+    let mut buf = vec![0u8; if chunked { CHUNK_SIZE } else { TRANSFER_BUFFER_SIZE }];
     let mut sent: u64 = 0;
     loop {
-        let n = f.read(&mut buf).map_err(|e| e.to_string())?;
+        let n = f.read(&mut buf)?;
         if n == 0 { break; }
-        stream.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        if chunked {
+            stream.write_all(&(n as u32).to_be_bytes()).map_err(AppError::network)?;
+        }
+        stream.write_all(&buf[..n]).map_err(AppError::network)?;
+        if chunked {
+            let mut ack = [0u8; 1];
+            stream.read_exact(&mut ack).map_err(|e| AppError::Network(format!("Receiver stopped acknowledging: {}", e)))?;
+            if &ack != CHUNK_ACK { return Err(AppError::Network("Unexpected chunk acknowledgement".into())); }
+        }
         sent += n as u64;
+        on_progress(sent, size);
         if sent >= size { break; }
     }
     Ok(())
 }
 
-fn recv_file(stream: &mut TcpStream, out_path: &Path) -> Result<(), String> {
+/// Minimum gap between progress callbacks so fast LANs don't flood the event channel.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives a length-prefixed file, reporting `(received, total)` through `on_progress`
+/// at most every `PROGRESS_INTERVAL` and once more when the transfer completes.
+fn recv_file(stream: &mut TcpStream, out_path: &Path, max_size: u64, chunked: bool, on_progress: &mut dyn FnMut(u64, u64)) -> Result<(), AppError> {
     let size = read_u64_be(stream)?;
-    let mut f = fs::File::create(out_path).map_err(|e| e.to_string())?;
+    if size > max_size {
+        return Err(AppError::Network(format!("Sender announced {} bytes but the offer declared {}", size, max_size)));
+    }
+    let mut f = fs::File::create(out_path)?;
     let mut remaining = size as i64;
-    let mut buf = [0u8; 8192];
+    let mut buf = vec![0u8; if chunked { CHUNK_SIZE } else { TRANSFER_BUFFER_SIZE }];
+    let mut last_progress = Instant::now();
+    if chunked {
+        stream.set_read_timeout(Some(CHUNK_ACK_TIMEOUT)).ok();
+    }
     while remaining > 0 {
-        let n = stream.read(&mut buf).map_err(|e| e.to_string())? as i64;
+        let n = if chunked {
+            let mut len_buf = [0u8; 4];
+            stream.read_exact(&mut len_buf).map_err(|e| AppError::Network(format!("Sender stalled: {}", e)))?;
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if len == 0 || len > CHUNK_SIZE || len as i64 > remaining {
+                return Err(AppError::Network(format!("Invalid chunk length {}", len)));
+            }
+            stream.read_exact(&mut buf[..len]).map_err(|e| AppError::Network(format!("Sender stalled: {}", e)))?;
+            stream.write_all(CHUNK_ACK).map_err(AppError::network)?;
+            len as i64
+        } else {
+            // Never read past the announced size, even if the peer keeps writing.
+            let want = buf.len().min(remaining as usize);
+            stream.read(&mut buf[..want]).map_err(AppError::network)? as i64
+        };
         if n == 0 { break; }
-        f.write_all(&buf[..n as usize]).map_err(|e| e.to_string())?;
+        f.write_all(&buf[..n as usize])?;
         remaining -= n;
+        if remaining == 0 || last_progress.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(size - remaining as u64, size);
+            last_progress = Instant::now();
+        }
+    }
+    if remaining > 0 {
+        return Err(AppError::Network(format!("Connection closed early: received {} of {} bytes", size as i64 - remaining, size)));
     }
     Ok(())
 }
@@ -228,80 +966,221 @@ struct TransferHeader {
     #[serde(skip_serializing_if = "Option::is_none")] note_title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")] note_preview: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")] notes_count: Option<u32>,
+    /// `Some("v2")` proposes chunked mode; absent means the original stream-once protocol.
+    #[serde(default, skip_serializing_if = "Option::is_none")] protocol: Option<String>,
+    /// The sender's device id. Self-reported, so it identifies rather than authenticates.
+    #[serde(default, skip_serializing_if = "Option::is_none")] sender_id: Option<String>,
+    /// Present when the archive is encrypted with the shared transfer passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")] encryption: Option<EncryptionParams>,
+}
+
+impl TransferHeader {
+    fn wants_chunked(&self) -> bool {
+        self.protocol.as_deref() == Some(PROTOCOL_V2)
+    }
 }
 
 struct PendingTransfer {
     stream: Option<TcpStream>,
     header: TransferHeader,
     peer: SocketAddr,
+    received_at: Instant,
 }
 
 static LISTENING: AtomicBool = AtomicBool::new(false);
 static RECEIVER_STOP: AtomicBool = AtomicBool::new(false);
+/// Port the transfer listener actually bound; 0 while the receive service is off.
+static LISTEN_PORT: AtomicU16 = AtomicU16::new(0);
 static PENDING: Lazy<Mutex<HashMap<String, PendingTransfer>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
-fn send_header_and_wait_ack(stream: &mut TcpStream, header: &TransferHeader) -> Result<(), String> {
-    let data = serde_json::to_vec(header).map_err(|e| e.to_string())?;
+/// Removes offers that have waited longer than `max_age` for the user to respond.
+fn take_expired(map: &mut HashMap<String, PendingTransfer>, max_age: Duration) -> Vec<(String, PendingTransfer)> {
+    let expired: Vec<String> = map.iter()
+        .filter(|(_, p)| p.received_at.elapsed() > max_age)
+        .map(|(id, _)| id.clone())
+        .collect();
+    expired.into_iter().filter_map(|id| map.remove(&id).map(|p| (id, p))).collect()
+}
+
+fn sweep_expired_offers(app: &AppHandle, max_age: Duration) {
+    let expired = take_expired(&mut PENDING.lock().unwrap(), max_age);
+    for (id, mut pending) in expired {
+        if let Some(mut stream) = pending.stream.take() {
+            let _ = stream.write_all(b"NO\n");
+        }
+        let _ = app.emit("share://recv_done", &serde_json::json!({"ok":false,"id":id,"message":format!("Offer from {} timed out", pending.peer)}));
+    }
+}
+
+/// Whether another offer would exceed `max_pending`. Expired offers are swept
+/// first so they give their slot back.
+fn pending_full(app: &AppHandle, max_age: Duration, max_pending: usize) -> bool {
+    sweep_expired_offers(app, max_age);
+    at_capacity(&PENDING.lock().unwrap(), max_pending)
+}
+
+fn at_capacity(map: &HashMap<String, PendingTransfer>, max_pending: usize) -> bool {
+    map.len() >= max_pending
+}
+
+/// What the receiver does with an offer before the user sees it.
+#[derive(Debug, PartialEq)]
+enum OfferDecision {
+    /// Refused with `NO`, for the reason given.
+    Reject(String),
+    /// Refused with `EN`: only one side encrypts, so the transfer can't succeed.
+    EncryptionMismatch(String),
+    /// Shown to the user, or accepted right away when trusted and `auto_accept_trusted` is on.
+    Offer { trusted: bool },
+}
+
+/// Checks an offer against the limits, the free disk space, the pending queue
+/// and the trust settings, in that order.
+fn screen_offer(header: &TransferHeader, peer: SocketAddr, settings: &Settings, max_bytes: u64, free: Option<u64>, pending_full: bool) -> OfferDecision {
+    if header.size > max_bytes {
+        return OfferDecision::Reject(format!("Rejected {} bytes from {}: exceeds the {} byte limit", header.size, peer, max_bytes));
+    }
+    if !has_room(free, header.size) {
+        return OfferDecision::Reject(format!("Rejected {} bytes from {}: not enough disk space", header.size, peer));
+    }
+    if pending_full {
+        return OfferDecision::Reject(format!("Rejected transfer from {}: too many pending transfers", peer));
+    }
+    // Device ids are broadcast in the clear, so anyone can claim one. Only an
+    // encrypted offer counts as trusted: it is imported only if it decrypts
+    // with our passphrase, which a spoofer doesn't have.
+    let known_id = header.sender_id.as_ref().is_some_and(|s| settings.trusted_peers.contains(s));
+    let trusted = known_id && header.encryption.is_some();
+    if !trusted && settings.reject_unknown_peers {
+        return OfferDecision::Reject(if known_id {
+            format!("Rejected unencrypted transfer from trusted device {}: trusted devices must encrypt with the shared transfer passphrase", peer)
+        } else {
+            format!("Rejected transfer from untrusted device {}", peer)
+        });
+    }
+    if header.encryption.is_some() != settings.transfer_passphrase.is_some() {
+        let reason = if header.encryption.is_some() { "it is encrypted but no transfer passphrase is set" } else { "encryption is required" };
+        return OfferDecision::EncryptionMismatch(format!("Rejected transfer from {}: {}", peer, reason));
+    }
+    OfferDecision::Offer { trusted }
+}
+
+/// Sends the header and waits for the receiver's answer. Returns whether the
+/// receiver agreed to chunked mode; older receivers always answer "OK".
+fn send_header_and_wait_ack(stream: &mut TcpStream, header: &TransferHeader) -> Result<bool, AppError> {
+    let data = serde_json::to_vec(header)?;
     let len: u32 = data.len() as u32;
-    stream.write_all(&len.to_be_bytes()).map_err(|e| e.to_string())?;
-    stream.write_all(&data).map_err(|e| e.to_string())?;
+    stream.write_all(&len.to_be_bytes()).map_err(AppError::network)?;
+    stream.write_all(&data).map_err(AppError::network)?;
     stream.flush().ok();
-    // Wait for small ACK "OK\n"
+    // Wait for small ACK "OK\n" (or "V2\n" when chunked mode was accepted)
     stream.set_read_timeout(Some(Duration::from_secs(120))).ok();
     let mut ack = [0u8; 3];
-    stream.read_exact(&mut ack).map_err(|e| e.to_string())?;
-    if &ack == b"NO\n" { return Err("Rejected by receiver".into()); }
-    if &ack != b"OK\n" { return Err("Receiver did not ACK".into()); }
-    Ok(())
+    stream.read_exact(&mut ack).map_err(AppError::network)?;
+    if &ack == b"NO\n" { return Err(AppError::Network("Rejected by receiver".into())); }
+    if &ack == b"EN\n" { return Err(AppError::Encryption("Encryption required: set the same transfer passphrase on both devices".into())); }
+    if &ack == b"V2\n" && header.wants_chunked() { return Ok(true); }
+    if &ack != b"OK\n" { return Err(AppError::Network("Receiver did not ACK".into())); }
+    Ok(false)
 }
 
-fn recv_header(stream: &mut TcpStream) -> Result<TransferHeader, String> {
+fn recv_header(stream: &mut TcpStream) -> Result<TransferHeader, AppError> {
     let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf).map_err(|e| e.to_string())?;
+    stream.read_exact(&mut len_buf).map_err(AppError::network)?;
     let len = u32::from_be_bytes(len_buf);
     let mut data = vec![0u8; len as usize];
-    stream.read_exact(&mut data).map_err(|e| e.to_string())?;
-    let header: TransferHeader = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
-    if header.magic != TRANSFER_MAGIC { return Err("Bad transfer header".into()); }
+    stream.read_exact(&mut data).map_err(AppError::network)?;
+    let header: TransferHeader = serde_json::from_slice(&data)?;
+    if header.magic != TRANSFER_MAGIC { return Err(AppError::Network("Bad transfer header".into())); }
     Ok(header)
 }
 
+/// The transfer port peers should connect to: the bound one while receiving, else the default.
+pub(crate) fn transfer_port() -> u16 {
+    match LISTEN_PORT.load(Ordering::SeqCst) {
+        0 => TRANSFER_PORT,
+        port => port,
+    }
+}
+
+/// Binds the preferred transfer port, falling back to an ephemeral one when it's taken
+/// (e.g. by a second instance). Discovery advertises whichever port was bound.
+fn bind_transfer_listener(app: &AppHandle) -> Result<TcpListener, AppError> {
+    bind_with_fallback(load_settings(app).transfer_port)
+}
+
+fn bind_with_fallback(preferred: u16) -> Result<TcpListener, AppError> {
+    TcpListener::bind(("0.0.0.0", preferred))
+        .or_else(|_| TcpListener::bind(("0.0.0.0", 0)))
+        .map_err(AppError::network)
+}
+
+/// Waits for one packet on `udp` and replies with `pong()` if it is a discovery ping
+/// and `discoverable()` allows answering. Returns whether a pong was sent.
+fn answer_ping(udp: &UdpSocket, discoverable: impl FnOnce() -> bool, pong: impl FnOnce() -> Vec<u8>) -> std::io::Result<bool> {
+    let mut buf = [0u8; 2048];
+    let (n, from) = udp.recv_from(&mut buf)?;
+    let is_ping = serde_json::from_slice::<DiscoveryPing>(&buf[..n]).is_ok_and(|msg| msg.magic == DISCOVERY_MAGIC && msg.kind == "ping");
+    if !is_ping || !discoverable() { return Ok(false); }
+    udp.send_to(&pong(), from)?;
+    Ok(true)
+}
+
+/// UDP socket that answers discovery pings. It stays bound to the wildcard address:
+/// on Linux a socket bound to one interface's address never sees broadcasts sent to
+/// that subnet, so per-interface sockets would hear less, not more. Address (and, on
+/// Unix, port) reuse lets a restarted receiver or a second instance bind right away
+/// instead of failing with "address in use" while the old socket lingers.
+fn bind_discovery_listener(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    Ok(socket.into())
+}
+
 #[tauri::command]
-pub fn start_receive_service(app: AppHandle) -> Result<String, String> {
+pub fn start_receive_service(app: AppHandle) -> Result<String, AppError> {
     if LISTENING.swap(true, Ordering::SeqCst) {
-        let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"listening"}));
+        let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"listening","port":transfer_port()}));
         return Ok("already".into());
     }
     RECEIVER_STOP.store(false, Ordering::SeqCst);
+    let listener = match bind_transfer_listener(&app) {
+        Ok(l) => l,
+        Err(e) => { LISTENING.store(false, Ordering::SeqCst); return Err(e); }
+    };
+    let port = listener.local_addr().map(|a| a.port()).unwrap_or(TRANSFER_PORT);
+    LISTEN_PORT.store(port, Ordering::SeqCst);
     let app_udp = app.clone();
+    let discovery_port = load_settings(&app).discovery_port;
     std::thread::spawn(move || {
-        let udp = match UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)) { Ok(s) => s, Err(e) => { let _=app_udp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":e.to_string()})); return; } };
-        let _ = app_udp.emit("share://recv_status", &serde_json::json!({"phase":"listening"}));
+        let udp = match bind_discovery_listener(discovery_port) { Ok(s) => s, Err(e) => { let _=app_udp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":e.to_string()})); return; } };
+        let _ = app_udp.emit("share://recv_status", &serde_json::json!({"phase":"listening","port":port}));
         udp.set_read_timeout(Some(Duration::from_millis(500))).ok();
         while !RECEIVER_STOP.load(Ordering::SeqCst) {
-            let mut buf = [0u8; 2048];
-            match udp.recv_from(&mut buf) {
-                Ok((n, from)) => {
-                    if let Ok(msg) = serde_json::from_slice::<DiscoveryPing>(&buf[..n]) {
-                        if msg.magic == DISCOVERY_MAGIC && msg.kind == "ping" {
-                            let pong = DiscoveryPing { magic: DISCOVERY_MAGIC.to_string(), kind: "pong".into(), name: host_name_fallback(), transfer_port: TRANSFER_PORT, id: Uuid::new_v4().to_string() };
-                            let pong_bytes = serde_json::to_vec(&pong).unwrap_or_default();
-                            let _ = udp.send_to(&pong_bytes, from);
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {
-                    // keep looping
-                }
-                Err(_) => { /* ignore */ }
-            }
+            // A timeout only means no ping arrived; like any other error it just loops.
+            let _ = answer_ping(
+                &udp,
+                // Read per ping so turning discoverability off applies to a running listener.
+                || load_settings(&app_udp).discoverable,
+                || {
+                    let count = notes_dir(&app_udp).ok().and_then(|dir| note_count(&dir));
+                    pong_bytes(&device_name(&app_udp), &device_id(&app_udp), port, count).unwrap_or_default()
+                },
+            );
         }
         let _ = app_udp.emit("share://recv_status", &serde_json::json!({"phase":"stopped"}));
     });
 
     let app_tcp = app.clone();
+    let settings = load_settings(&app);
+    let offer_timeout = Duration::from_secs(settings.pending_offer_timeout_secs);
+    let max_transfer_bytes = settings.max_transfer_bytes;
+    let max_pending = settings.max_pending_offers;
     std::thread::spawn(move || {
-        let listener = match TcpListener::bind(("0.0.0.0", TRANSFER_PORT)) { Ok(l) => l, Err(e) => { let _=app_tcp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":e.to_string()})); return; } };
         let _ = listener.set_nonblocking(true);
         while !RECEIVER_STOP.load(Ordering::SeqCst) {
             match listener.accept() {
@@ -309,18 +1188,42 @@ pub fn start_receive_service(app: AppHandle) -> Result<String, String> {
                     let _ = stream.set_read_timeout(Some(Duration::from_secs(180)));
                     match recv_header(&mut stream) {
                         Ok(header) => {
+                            let settings = load_settings(&app_tcp);
+                            let full = pending_full(&app_tcp, offer_timeout, max_pending);
+                            let trusted = match screen_offer(&header, peer_addr, &settings, max_transfer_bytes, free_space(&app_tcp), full) {
+                                OfferDecision::Offer { trusted } => trusted,
+                                OfferDecision::Reject(message) => {
+                                    let _ = stream.write_all(b"NO\n");
+                                    let _ = app_tcp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":message}));
+                                    continue;
+                                }
+                                OfferDecision::EncryptionMismatch(message) => {
+                                    let _ = stream.write_all(b"EN\n");
+                                    let _ = app_tcp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":message}));
+                                    continue;
+                                }
+                            };
                             let id = Uuid::new_v4().to_string();
                             {
                                 let mut map = PENDING.lock().unwrap();
-                                map.insert(id.clone(), PendingTransfer { stream: Some(stream), header: header.clone(), peer: peer_addr });
+                                map.insert(id.clone(), PendingTransfer { stream: Some(stream), header: header.clone(), peer: peer_addr, received_at: Instant::now() });
+                            }
+                            if trusted && settings.auto_accept_trusted {
+                                let _ = app_tcp.emit("share://recv_status", &serde_json::json!({"phase":"auto_accepted","id":id,"peer":peer_addr.to_string()}));
+                                let app_accept = app_tcp.clone();
+                                // Receiving blocks until the archive is in, so keep it off the accept loop.
+                                std::thread::spawn(move || { let _ = accept_incoming_transfer(app_accept, id, true, None, None); });
+                            } else {
+                                let _ = app_tcp.emit("share://recv_offer", &serde_json::json!({
+                                    "id": id,
+                                    "peer": peer_addr.to_string(),
+                                    "kind": header.kind,
+                                    "size": header.size,
+                                    "filename": header.filename,
+                                    "senderId": header.sender_id,
+                                    "trusted": trusted
+                                }));
                             }
-                            let _ = app_tcp.emit("share://recv_offer", &serde_json::json!({
-                                "id": id,
-                                "peer": peer_addr.to_string(),
-                                "kind": header.kind,
-                                "size": header.size,
-                                "filename": header.filename
-                            }));
                         }
                         Err(e) => {
                             let _ = app_tcp.emit("share://recv_done", &serde_json::json!({"ok":false,"message":format!("Bad header: {}", e)}));
@@ -332,6 +1235,7 @@ pub fn start_receive_service(app: AppHandle) -> Result<String, String> {
                 }
                 Err(_) => { /* ignore transient */ }
             }
+            sweep_expired_offers(&app_tcp, offer_timeout);
         }
     });
 
@@ -339,118 +1243,225 @@ pub fn start_receive_service(app: AppHandle) -> Result<String, String> {
 }
 
 #[tauri::command]
-pub fn stop_receive_service(app: AppHandle) -> Result<(), String> {
+pub fn stop_receive_service(app: AppHandle) -> Result<(), AppError> {
     if !LISTENING.load(Ordering::SeqCst) {
         let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"stopped"}));
         return Ok(());
     }
     RECEIVER_STOP.store(true, Ordering::SeqCst);
     LISTENING.store(false, Ordering::SeqCst);
+    LISTEN_PORT.store(0, Ordering::SeqCst);
     let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"stopping"}));
     Ok(())
 }
 
+/// Extracts a notes archive into `temp_extract` and merges it into `notes_dir_path`,
+/// or replaces its notes in `ImportMode::Replace`. Shared by received transfers and manual imports.
+fn import_archive(notes_dir_path: &Path, zip_path: &Path, temp_extract: &Path, mode: ImportMode, strategy: MergeStrategy, skew: Duration, peer_label: &str) -> Result<MergeOutcome, AppError> {
+    let _guard = crate::lock_notes_dir(notes_dir_path)?;
+    let _ = fs::remove_dir_all(temp_extract);
+    fs::create_dir_all(temp_extract)?;
+    let _cleanup = ScratchGuard(temp_extract.to_path_buf());
+    unzip_into(temp_extract, zip_path)?;
+    // A sender that left the index out (or wrote an empty file) still sent notes;
+    // rebuild what we can from the files. An empty array really means no notes.
+    let incoming_index_str = fs::read_to_string(temp_extract.join("index.json")).unwrap_or_default();
+    let incoming_index = if incoming_index_str.trim().is_empty() {
+        index_from_files(temp_extract)?
+    } else {
+        parse_index(&incoming_index_str)?.0
+    };
+    let outcome = match mode {
+        ImportMode::Merge => merge_index(notes_dir_path, temp_extract, &incoming_index, strategy, skew, peer_label)?,
+        ImportMode::Replace => replace_index(notes_dir_path, temp_extract, &incoming_index)?,
+    };
+    // Only notes whose incoming metadata won the merge replace local files, so the
+    // index and the files always agree. Kept-both copies were already written by the merge.
+    // The index already lists these notes, so a failed copy fails the whole import
+    // rather than reporting success over a missing or stale file.
+    for id in outcome.added.iter().chain(&outcome.updated) {
+        let src = note_file(temp_extract, id);
+        if !src.exists() { continue; }
+        // The sender may have changed the note's format; don't leave the old file behind.
+        let dest = notes_dir_path.join(file_name_of(&src));
+        let old = note_file(notes_dir_path, id);
+        if old != dest && old.exists() { let _ = fs::remove_file(old); }
+        fs::copy(&src, dest)?;
+        // Attachments follow their note; kept-both copies got theirs in the merge.
+        for (file, _) in attachment_files(temp_extract, id) {
+            let dest_dir = attachment_dir(notes_dir_path, id);
+            fs::create_dir_all(&dest_dir)?;
+            if let Some(name) = file.file_name() { fs::copy(&file, dest_dir.join(name))?; }
+        }
+    }
+    Ok(outcome)
+}
+
 #[tauri::command]
-pub fn accept_incoming_transfer(app: AppHandle, id: String, accept: bool) -> Result<(), String> {
+pub fn accept_incoming_transfer(app: AppHandle, id: String, accept: bool, strategy: Option<MergeStrategy>, mode: Option<ImportMode>) -> Result<(), AppError> {
     let notes_dir_path = notes_dir(&app)?;
-    let mut map = PENDING.lock().unwrap();
-    let mut pending = map.remove(&id).ok_or_else(|| "No such transfer".to_string())?;
-    let mut stream = pending.stream.take().ok_or_else(|| "Stream missing".to_string())?;
+    let mut pending = PENDING.lock().unwrap().remove(&id).ok_or_else(|| AppError::NotFound("No such transfer".into()))?;
+    let mut stream = pending.stream.take().ok_or_else(|| AppError::Conflict("Stream missing".into()))?;
     if !accept {
         let _ = stream.write_all(b"NO\n");
         let _ = app.emit("share://recv_done", &serde_json::json!({"ok":false,"message":"Rejected"}));
         return Ok(());
     }
     // ACK and receive
-    stream.write_all(b"OK\n").map_err(|e| e.to_string())?;
-    let zip_tmp = notes_dir_path.join("incoming_notes.zip");
-    recv_file(&mut stream, &zip_tmp)?;
-    let temp_extract = notes_dir_path.join("incoming_tmp");
-    let _ = fs::remove_dir_all(&temp_extract);
-    fs::create_dir_all(&temp_extract).map_err(|e| e.to_string())?;
-    unzip_into(&temp_extract, &zip_tmp)?;
-    let incoming_index_path = temp_extract.join("index.json");
-    let incoming_index_str = fs::read_to_string(&incoming_index_path).map_err(|e| e.to_string())?;
-    let incoming_index: Vec<StoredNoteMetadata> = serde_json::from_str(&incoming_index_str).map_err(|e| e.to_string())?;
-    if let Ok(rd) = fs::read_dir(&temp_extract) {
-        for entry in rd { if let Ok(entry) = entry { let path = entry.path(); if path.extension().and_then(|s| s.to_str()) == Some("md") { if let Some(file_name) = path.file_name() { let _ = fs::copy(&path, notes_dir_path.join(file_name)); } } } }
-    }
-    let dest_index_path = notes_dir_path.join("index.json");
-    merge_index(&dest_index_path, &incoming_index)?;
-    let _ = fs::remove_file(zip_tmp);
-    let _ = fs::remove_dir_all(temp_extract);
-    let _ = app.emit("share://recv_done", &serde_json::json!({"ok":true,"message":format!("Received {} bytes from {}", pending.header.size, pending.peer)}));
+    let chunked = pending.header.wants_chunked();
+    stream.write_all(if chunked { b"V2\n" } else { b"OK\n" }).map_err(AppError::network)?;
+    let zip_tmp = scratch_path(&app, "incoming_notes.zip")?;
+    let _zip_cleanup = ScratchGuard(zip_tmp.clone());
+    let temp_extract = scratch_path(&app, "incoming")?;
+    let mut meter = RateMeter::new();
+    let heartbeat = Heartbeat::start(app.clone(), id.clone());
+    let mut report = |received: u64, total: u64| {
+        let (rate, eta) = meter.record(received, total);
+        let _ = app.emit("share://recv_status", &serde_json::json!({"phase":"receiving","id":id,"received":received,"total":total,"bytesPerSec":rate,"etaSecs":eta}));
+        heartbeat.touch();
+    };
+    let peer_label = pending.peer.ip().to_string();
+    let record = TransferRecord::new(TransferDirection::Received, peer_label.clone(), pending.header.kind.clone(), pending.header.size);
+    let received = recv_file(&mut stream, &zip_tmp, pending.header.size, chunked, &mut report).and_then(|()| {
+        match (&pending.header.encryption, load_settings(&app).transfer_passphrase) {
+            (Some(params), Some(passphrase)) => decrypt_file(&zip_tmp, &passphrase, params),
+            (Some(_), None) => Err(AppError::Encryption("Transfer is encrypted but no passphrase is set".into())),
+            (None, _) => Ok(()),
+        }
+    });
+    drop(heartbeat);
+    if let Err(e) = &received {
+        let _ = app.emit("share://recv_done", &serde_json::json!({"ok":false,"message":format!("Transfer from {} failed: {}", pending.peer, e)}));
+        record_transfer(&app, record.outcome(&received));
+        return received;
+    }
+    let skew = Duration::from_secs(load_settings(&app).merge_clock_skew_secs);
+    let imported = import_archive(&notes_dir_path, &zip_tmp, &temp_extract, mode.unwrap_or_default(), strategy.unwrap_or_default(), skew, &peer_label);
+    let notes = imported.as_ref().ok().map(|o| (o.added.len() + o.updated.len() + o.kept_both.len()) as u32);
+    record_transfer(&app, record.notes(notes).outcome(&imported));
+    let outcome = match imported {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            let _ = app.emit("share://recv_done", &serde_json::json!({"ok":false,"message":format!("Couldn't import notes from {}: {}", pending.peer, e)}));
+            return Err(e);
+        }
+    };
+    let _ = app.emit("share://recv_done", &serde_json::json!({"ok":true,"message":format!("{} from {}", outcome.summary(), pending.peer),"added":outcome.added,"updated":outcome.updated,"keptBoth":outcome.kept_both,"stats":outcome.stats()}));
     Ok(())
 }
 
-#[tauri::command]
-pub fn send_all_notes(app: AppHandle, wait_secs: Option<u64>) -> Result<String, String> {
-    // 1) Broadcast discovery ping on all interfaces
-    let timeout = wait_secs.unwrap_or(10);
-    let udp = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| e.to_string())?;
-    udp.set_broadcast(true).ok();
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub path: String,
+    pub notes: u32,
+    /// Note files that couldn't be read as text and were left out.
+    pub skipped: Vec<String>,
+}
 
-    let ping = DiscoveryPing {
-        magic: DISCOVERY_MAGIC.to_string(),
-        kind: "ping".into(),
-        name: host_name_fallback(),
-        transfer_port: TRANSFER_PORT,
-        id: Uuid::new_v4().to_string(),
-    };
-    let bytes = serde_json::to_vec(&ping).map_err(|e| e.to_string())?;
-    for addr in directed_broadcasts() {
-        let _ = udp.send_to(&bytes, addr);
+/// The chosen export path, with `.zip` added when the user typed no extension.
+fn export_target(out_path: &str) -> PathBuf {
+    let mut out = PathBuf::from(out_path);
+    if out.extension().is_none() { out.set_extension("zip"); }
+    out
+}
+
+/// Writes a backup of every note to `out_path`; unlike transfer archives it is kept.
+#[tauri::command]
+pub fn export_notes_zip(app: AppHandle, out_path: String) -> Result<ExportResult, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    let out = export_target(&out_path);
+    let ArchivedNotes { notes, skipped } = zip_notes_dir(&notes_dir_path, &out)?;
+    Ok(ExportResult { path: out.to_string_lossy().into_owned(), notes, skipped })
+}
+
+#[tauri::command]
+pub fn import_notes_zip(app: AppHandle, zip_path: String, strategy: Option<MergeStrategy>, mode: Option<ImportMode>) -> Result<MergeOutcome, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    let zip_path = Path::new(&zip_path);
+    let label = zip_path.file_name().and_then(|s| s.to_str()).unwrap_or("import").to_string();
+    let skew = Duration::from_secs(load_settings(&app).merge_clock_skew_secs);
+    import_archive(&notes_dir_path, zip_path, &scratch_path(&app, "import")?, mode.unwrap_or_default(), strategy.unwrap_or_default(), skew, &label)
+}
+
+/// Runs blocking socket work on the async runtime's blocking pool so the
+/// command doesn't tie up the thread that dispatched it.
+async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T, AppError> + Send + 'static) -> Result<T, AppError> {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::Io(e.to_string()))?
+}
+
+/// Waits up to `timeout` for a valid pong. Anything else arriving on the socket,
+/// like our own ping echoed back or unrelated traffic, is skipped.
+fn first_pong(udp: &UdpSocket, timeout: Duration) -> Result<(SocketAddr, DiscoveryPing), AppError> {
+    udp.set_read_timeout(Some(Duration::from_millis(500))).ok();
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let mut buf = [0u8; 2048];
+        match udp.recv_from(&mut buf) {
+            Ok((n, from)) => {
+                if let Ok(msg) = serde_json::from_slice::<DiscoveryPing>(&buf[..n]) {
+                    if msg.magic == DISCOVERY_MAGIC && msg.kind == "pong" {
+                        return Ok((from, msg));
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(AppError::Network(format!("No receiver found: {}", e))),
+        }
     }
+    Err(AppError::Network("No receiver found".into()))
+}
+
+fn send_all_notes_blocking(app: AppHandle, wait_secs: Option<u64>) -> Result<String, AppError> {
+    // 1) Broadcast discovery ping on all interfaces
+    let timeout = wait_secs.unwrap_or(10);
+    let udp = discovery_socket(&app)?;
+
+    broadcast_ping(&udp, &device_name(&app), &device_id(&app), load_settings(&app).discovery_port)?;
 
     // 2) Wait for first pong
-    udp.set_read_timeout(Some(Duration::from_secs(timeout))).ok();
-    let mut buf = [0u8; 2048];
-    let (n, from) = udp.recv_from(&mut buf).map_err(|e| format!("No receiver found: {}", e))?;
-    let msg: DiscoveryPing = serde_json::from_slice(&buf[..n]).map_err(|e| e.to_string())?;
-    if msg.magic != DISCOVERY_MAGIC || msg.kind != "pong" {
-        return Err("Unexpected discovery response".into());
-    }
+    let (from, msg) = first_pong(&udp, Duration::from_secs(timeout))?;
 
     // 3) Zip notes dir
     let notes_dir_path = notes_dir(&app)?;
-    let tmp_zip = notes_dir_path.join("outgoing_notes.zip");
+    let tmp_zip = scratch_path(&app, "outgoing_notes.zip")?;
+    let _cleanup = ScratchGuard(tmp_zip.clone());
     zip_notes_dir(&notes_dir_path, &tmp_zip)?;
 
-    // 4) Connect and send
+    // 4) Send with the header handshake, encrypted when a passphrase is set
     let target = SocketAddr::new(from.ip(), msg.transfer_port);
-    let mut stream = TcpStream::connect(target).map_err(|e| e.to_string())?;
-    send_file(&mut stream, &tmp_zip)?;
-
-    // Cleanup
-    let _ = fs::remove_file(tmp_zip);
+    send_zip_to(&app, &tmp_zip, "all", &from.ip().to_string(), msg.transfer_port)?;
 
-    let fmt = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
-    let ts = time::OffsetDateTime::now_utc().format(fmt).unwrap_or_default();
-    Ok(format!("Sent notes to {} at {}", target, ts))
+    Ok(format!("Sent notes to {} at {}", target, crate::now_timestamp()))
 }
 
 #[tauri::command]
-pub fn discover_receivers(wait_secs: Option<u64>) -> Result<Vec<PeerInfo>, String> {
+pub async fn send_all_notes(app: AppHandle, wait_secs: Option<u64>) -> Result<String, AppError> {
+    run_blocking(move || send_all_notes_blocking(app, wait_secs)).await
+}
+
+fn discover_receivers_blocking(app: AppHandle, wait_secs: Option<u64>) -> Result<Vec<PeerInfo>, AppError> {
     let timeout = wait_secs.unwrap_or(3);
-    let udp = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| e.to_string())?;
-    udp.set_broadcast(true).ok();
+    let stops = DISCOVERY_STOPS.load(Ordering::SeqCst);
+    let udp = discovery_socket(&app)?;
 
-    let ping = DiscoveryPing {
-        magic: DISCOVERY_MAGIC.to_string(),
-        kind: "ping".into(),
-        name: host_name_fallback(),
-        transfer_port: TRANSFER_PORT,
-        id: Uuid::new_v4().to_string(),
-    };
-    let bytes = serde_json::to_vec(&ping).map_err(|e| e.to_string())?;
-    for addr in directed_broadcasts() { let _ = udp.send_to(&bytes, addr); }
+    broadcast_ping(&udp, &device_name(&app), &device_id(&app), load_settings(&app).discovery_port)?;
 
+    // `stop_discovery` ends the wait early; peers found so far are still returned.
+    Ok(collect_pongs(&udp, Duration::from_secs(timeout), || DISCOVERY_STOPS.load(Ordering::SeqCst) != stops))
+}
+
+/// Gathers the peers answering on `udp` until `timeout` passes or `stopped()` turns true,
+/// which is checked at least every half second.
+fn collect_pongs(udp: &UdpSocket, timeout: Duration, stopped: impl Fn() -> bool) -> Vec<PeerInfo> {
     udp.set_read_timeout(Some(Duration::from_millis(500))).ok();
     let start = std::time::Instant::now();
     let mut peers: Vec<PeerInfo> = Vec::new();
     let mut seen = std::collections::HashSet::<String>::new();
 
-    while start.elapsed() < Duration::from_secs(timeout) {
+    while start.elapsed() < timeout && !stopped() {
         let mut buf = [0u8; 2048];
         match udp.recv_from(&mut buf) {
             Ok((n, from)) => {
@@ -458,7 +1469,7 @@ pub fn discover_receivers(wait_secs: Option<u64>) -> Result<Vec<PeerInfo>, Strin
                     if msg.magic == DISCOVERY_MAGIC && msg.kind == "pong" {
                         let ip = from.ip().to_string();
                         if seen.insert(format!("{}:{}", ip, msg.transfer_port)) {
-                            peers.push(PeerInfo { name: msg.name, ip, port: msg.transfer_port, id: msg.id });
+                            peers.push(PeerInfo { name: msg.name, ip, port: msg.transfer_port, id: msg.id, note_count: msg.note_count });
                         }
                     }
                 }
@@ -472,113 +1483,1535 @@ pub fn discover_receivers(wait_secs: Option<u64>) -> Result<Vec<PeerInfo>, Strin
             }
         }
     }
-    Ok(peers)
+    peers
 }
 
-fn send_zip_to(zip_path: &Path, ip: &str, port: u16) -> Result<String, String> {
-    let target: SocketAddr = format!("{}:{}", ip, port).parse::<SocketAddr>().map_err(|e| e.to_string())?;
-    let mut stream = TcpStream::connect(target).map_err(|e| e.to_string())?;
-    let size = fs::metadata(zip_path).map_err(|e| e.to_string())?.len();
-    let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "all".into(), size, filename: zip_path.file_name().and_then(|s| s.to_str()).unwrap_or("notes.zip").into(), note_title: None, note_preview: None, notes_count: None };
-    send_header_and_wait_ack(&mut stream, &header)?;
-    send_file(&mut stream, zip_path)?;
-    Ok(format!("Sent to {}", target))
+#[tauri::command]
+pub async fn discover_receivers(app: AppHandle, wait_secs: Option<u64>) -> Result<Vec<PeerInfo>, AppError> {
+    run_blocking(move || discover_receivers_blocking(app, wait_secs)).await
 }
 
 #[tauri::command]
-pub fn send_all_notes_to(app: AppHandle, ip: String, port: u16) -> Result<String, String> {
-    let notes_dir_path = notes_dir(&app)?;
-    let tmp_zip = notes_dir_path.join("outgoing_notes.zip");
-    zip_notes_dir(&notes_dir_path, &tmp_zip)?;
-    let res = send_zip_to(&tmp_zip, &ip, port);
-    let _ = fs::remove_file(tmp_zip);
-    res
+pub fn device_info(app: AppHandle) -> Result<DeviceInfo, AppError> {
+    Ok(DeviceInfo {
+        name: device_name(&app),
+        ips: local_ipv4s().into_iter().map(|(ip, _)| ip.to_string()).collect(),
+        discovery_port: load_settings(&app).discovery_port,
+        transfer_port: transfer_port(),
+    })
 }
 
 #[tauri::command]
-pub fn send_note_to(app: AppHandle, note_id: String, ip: String, port: u16) -> Result<String, String> {
-    let notes_dir_path = notes_dir(&app)?;
-    let tmp_zip = notes_dir_path.join("outgoing_single.zip");
-    zip_single_note(&notes_dir_path, &note_id, &tmp_zip)?;
-    let res = send_zip_to(&tmp_zip, &ip, port);
-    let _ = fs::remove_file(tmp_zip);
-    res
+pub fn get_device_name(app: AppHandle) -> Result<String, AppError> {
+    Ok(device_name(&app))
 }
 
+/// Stores the name shown to peers; an empty name reverts to the hostname.
 #[tauri::command]
-pub fn start_send_all_notes_to(app: AppHandle, ip: String, port: u16) -> Result<(), String> {
-    let app_clone = app.clone();
+pub fn set_device_name(app: AppHandle, name: String) -> Result<(), AppError> {
+    let mut settings = load_settings(&app);
+    let name = name.trim();
+    settings.device_name = if name.is_empty() { None } else { Some(name.to_string()) };
+    save_settings(&app, &settings)
+}
+
+/// Whether a receiving device answers discovery pings. Takes effect immediately,
+/// without restarting the receive service; transfers are accepted either way.
+#[tauri::command]
+pub fn set_discoverable(app: AppHandle, discoverable: bool) -> Result<(), AppError> {
+    let mut settings = load_settings(&app);
+    settings.discoverable = discoverable;
+    save_settings(&app, &settings)
+}
+
+/// Sets the passphrase used to encrypt transfers; an empty one turns encryption off.
+#[tauri::command]
+pub fn set_transfer_passphrase(app: AppHandle, passphrase: String) -> Result<(), AppError> {
+    let mut settings = load_settings(&app);
+    settings.transfer_passphrase = if passphrase.is_empty() { None } else { Some(passphrase) };
+    save_settings(&app, &settings)
+}
+
+#[tauri::command]
+pub fn list_trusted_peers(app: AppHandle) -> Result<Vec<String>, AppError> {
+    Ok(load_settings(&app).trusted_peers)
+}
+
+/// Marks a device (by its discovery id) as trusted; trusting it twice is a no-op.
+/// Trust only takes effect for encrypted transfers, since the id itself isn't authenticated.
+#[tauri::command]
+pub fn trust_peer(app: AppHandle, id: String) -> Result<(), AppError> {
+    let id = id.trim();
+    if id.is_empty() {
+        return Err(AppError::InvalidInput("Peer id is empty".into()));
+    }
+    let mut settings = load_settings(&app);
+    if !settings.trusted_peers.iter().any(|p| p == id) {
+        settings.trusted_peers.push(id.to_string());
+        save_settings(&app, &settings)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn untrust_peer(app: AppHandle, id: String) -> Result<(), AppError> {
+    let mut settings = load_settings(&app);
+    settings.trusted_peers.retain(|p| p != &id);
+    save_settings(&app, &settings)
+}
+
+#[tauri::command]
+pub fn list_favorite_peers(app: AppHandle) -> Vec<FavoritePeer> {
+    load_settings(&app).favorite_peers
+}
+
+/// Saves a discovered peer as a favorite, or refreshes its name and address if it already is one.
+#[tauri::command]
+pub fn add_favorite_peer(app: AppHandle, peer: PeerInfo) -> Result<Vec<FavoritePeer>, AppError> {
+    let mut settings = load_settings(&app);
+    add_favorite(&mut settings.favorite_peers, peer)?;
+    save_settings(&app, &settings)?;
+    Ok(settings.favorite_peers)
+}
+
+fn add_favorite(favorites: &mut Vec<FavoritePeer>, peer: PeerInfo) -> Result<(), AppError> {
+    if peer.id.trim().is_empty() {
+        return Err(AppError::InvalidInput("Peer id is empty".into()));
+    }
+    let favorite = FavoritePeer { name: peer.name, id: peer.id, ip: peer.ip, port: peer.port };
+    match favorites.iter_mut().find(|f| f.id == favorite.id) {
+        Some(existing) => *existing = favorite,
+        None => favorites.push(favorite),
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_favorite_peer(app: AppHandle, id: String) -> Result<Vec<FavoritePeer>, AppError> {
+    let mut settings = load_settings(&app);
+    settings.favorite_peers.retain(|f| f.id != id);
+    save_settings(&app, &settings)?;
+    Ok(settings.favorite_peers)
+}
+
+/// Short scan used to find a favorite's current address.
+const FAVORITE_DISCOVERY_SECS: u64 = 2;
+
+/// Where to reach a favorite now. With `rediscover`, a quick scan looks for its id
+/// and a fresh address is saved; otherwise, or if it doesn't answer, the stored
+/// address is used as is.
+fn resolve_favorite(app: &AppHandle, id: &str, rediscover: bool) -> Result<(String, u16), AppError> {
+    let mut settings = load_settings(app);
+    let pos = settings
+        .favorite_peers
+        .iter()
+        .position(|f| f.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("Favorite peer {id} not found")))?;
+    if rediscover {
+        let peers = discover_receivers_blocking(app.clone(), Some(FAVORITE_DISCOVERY_SECS)).unwrap_or_default();
+        if refresh_favorite(&mut settings.favorite_peers[pos], &peers) {
+            save_settings(app, &settings)?;
+        }
+    }
+    let favorite = &settings.favorite_peers[pos];
+    Ok((favorite.ip.clone(), favorite.port))
+}
+
+/// Moves `favorite` to the address of the scanned peer with its id. Returns
+/// whether the address changed; a favorite that didn't answer keeps its old one.
+fn refresh_favorite(favorite: &mut FavoritePeer, peers: &[PeerInfo]) -> bool {
+    let Some(peer) = peers.iter().find(|p| p.id == favorite.id) else { return false };
+    if favorite.ip == peer.ip && favorite.port == peer.port {
+        return false;
+    }
+    favorite.ip = peer.ip.clone();
+    favorite.port = peer.port;
+    true
+}
+
+/// Sends every note to a favorite peer; `rediscover` (on by default) first looks
+/// up the peer by id in case its address changed.
+#[tauri::command]
+pub async fn send_all_notes_to_favorite(app: AppHandle, id: String, rediscover: Option<bool>) -> Result<String, AppError> {
+    run_blocking(move || {
+        let (ip, port) = resolve_favorite(&app, &id, rediscover.unwrap_or(true))?;
+        send_all_notes_to_blocking(app, ip, port)
+    })
+    .await
+}
+
+static DISCOVERY_GEN: AtomicU64 = AtomicU64::new(0);
+/// Bumped only by `stop_discovery`, so one-shot scans end early when the user
+/// gives up but aren't cut short by a background scan restarting.
+static DISCOVERY_STOPS: AtomicU64 = AtomicU64::new(0);
+const DISCOVERY_PING_INTERVAL: Duration = Duration::from_secs(2);
+const PEER_LOST_AFTER: Duration = Duration::from_secs(7);
+
+/// Keeps pinging in the background and reports peers as they appear and go quiet.
+/// Starting again replaces the running scan rather than adding a second one.
+#[tauri::command]
+pub fn start_discovery(app: AppHandle) -> Result<String, AppError> {
+    let generation = DISCOVERY_GEN.fetch_add(1, Ordering::SeqCst) + 1;
+    let udp = discovery_socket(&app)?;
+    udp.set_read_timeout(Some(Duration::from_millis(500))).ok();
+    let name = device_name(&app);
+    let id = device_id(&app);
+    let discovery_port = load_settings(&app).discovery_port;
     std::thread::spawn(move || {
-        let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"preparing"}));
-        let notes_dir_path = match notes_dir(&app_clone) { Ok(p)=>p, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); return; } };
-        let tmp_zip = notes_dir_path.join("outgoing_notes.zip");
-        if let Err(e) = zip_notes_dir(&notes_dir_path, &tmp_zip) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); return; }
-        let size = fs::metadata(&tmp_zip).ok().and_then(|m| Some(m.len())).unwrap_or(0);
-        // count notes
-        let idx_count = fs::read_to_string(notes_dir_path.join("index.json")).ok().and_then(|s| serde_json::from_str::<Vec<StoredNoteMetadata>>(&s).ok()).map(|v| v.len() as u32);
-        let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"connecting","bytes":size}));
-        match TcpStream::connect(format!("{}:{}", ip, port)) {
-            Ok(mut stream) => {
-                let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"handshake"}));
-                let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "all".into(), size, filename: "outgoing_notes.zip".into(), note_title: None, note_preview: None, notes_count: idx_count };
-                if let Err(e) = send_header_and_wait_ack(&mut stream, &header) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); let _=fs::remove_file(&tmp_zip); return; }
-                // stream file with progress
-                let mut f = match fs::File::open(&tmp_zip){ Ok(f)=>f, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; } };
-                if write_u64_be(&mut stream, size).is_err() { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":"Failed to write size"})); let _=fs::remove_file(&tmp_zip); return; }
-                let mut buf = [0u8; 8192];
-                let mut sent: u64 = 0;
-                loop {
-                    let n = match f.read(&mut buf) { Ok(n)=>n, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; } };
-                    if n==0 { break; }
-                    if let Err(e) = stream.write_all(&buf[..n]) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; }
-                    sent += n as u64;
-                    let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"sending","sent":sent,"total":size}));
+        let mut peers: HashMap<String, (PeerInfo, Instant)> = HashMap::new();
+        let mut last_ping: Option<Instant> = None;
+        while DISCOVERY_GEN.load(Ordering::SeqCst) == generation {
+            if last_ping.is_none_or(|t| t.elapsed() >= DISCOVERY_PING_INTERVAL) {
+                let _ = broadcast_ping(&udp, &name, &id, discovery_port);
+                last_ping = Some(Instant::now());
+            }
+            let mut buf = [0u8; 2048];
+            if let Ok((n, from)) = udp.recv_from(&mut buf) {
+                if let Ok(msg) = serde_json::from_slice::<DiscoveryPing>(&buf[..n]) {
+                    if msg.magic == DISCOVERY_MAGIC && msg.kind == "pong" {
+                        let ip = from.ip().to_string();
+                        let key = format!("{}:{}", ip, msg.transfer_port);
+                        match peers.get_mut(&key) {
+                            Some((_, seen)) => *seen = Instant::now(),
+                            None => {
+                                let peer = PeerInfo { name: msg.name, ip, port: msg.transfer_port, id: msg.id, note_count: msg.note_count };
+                                let _ = app.emit("share://peer_found", &peer);
+                                peers.insert(key, (peer, Instant::now()));
+                            }
+                        }
+                    }
                 }
-                let _ = app_clone.emit("share://send_done", &serde_json::json!({"ok":true,"message":"Sent"}));
             }
-            Err(e) => { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); }
+            peers.retain(|_, (peer, seen)| {
+                if seen.elapsed() < PEER_LOST_AFTER { return true; }
+                let _ = app.emit("share://peer_lost", &*peer);
+                false
+            });
         }
-        let _ = fs::remove_file(&tmp_zip);
     });
+    Ok("started".into())
+}
+
+#[tauri::command]
+pub fn stop_discovery() -> Result<(), AppError> {
+    // Bumping the counters makes any running scan exit on its next loop.
+    DISCOVERY_GEN.fetch_add(1, Ordering::SeqCst);
+    DISCOVERY_STOPS.fetch_add(1, Ordering::SeqCst);
     Ok(())
 }
 
+/// Connects to `target`, first binding to `source` so the connection leaves through that interface.
+fn connect_from(source: Option<Ipv4Addr>, target: SocketAddr) -> std::io::Result<TcpStream> {
+    let Some(source) = source else { return TcpStream::connect(target) };
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    socket.bind(&SocketAddr::from((source, 0)).into())?;
+    socket.connect(&target.into())?;
+    Ok(socket.into())
+}
+
+/// Attempts before a send gives up on reaching the receiver.
+const CONNECT_ATTEMPTS: u32 = 3;
+const CONNECT_BACKOFF_START: Duration = Duration::from_millis(500);
+const CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(2);
+
+/// Connects to a receiver, retrying with exponential backoff because its listener
+/// may still be binding right after the user switched to receive mode.
+fn connect_with_retry(app: &AppHandle, target: SocketAddr, transfer_id: Option<&str>) -> Result<TcpStream, AppError> {
+    let source = source_ip(app);
+    with_backoff(
+        || connect_from(source, target),
+        |attempt, delay| {
+            let status = with_transfer_id(serde_json::json!({"phase":"retrying","attempt":attempt}), transfer_id);
+            let _ = app.emit("share://send_status", &status);
+            std::thread::sleep(delay);
+        },
+    )
+}
+
+/// Calls `connect` up to `CONNECT_ATTEMPTS` times. Between attempts, `wait` gets
+/// the failed attempt's number and how long to pause before the next one.
+fn with_backoff<T>(mut connect: impl FnMut() -> std::io::Result<T>, mut wait: impl FnMut(u32, Duration)) -> Result<T, AppError> {
+    let mut delay = CONNECT_BACKOFF_START;
+    let mut attempt = 1;
+    loop {
+        match connect() {
+            Ok(stream) => return Ok(stream),
+            Err(e) if attempt >= CONNECT_ATTEMPTS => return Err(AppError::network(e)),
+            Err(_) => {
+                wait(attempt, delay);
+                delay = (delay * 2).min(CONNECT_BACKOFF_MAX);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Encrypts the archive into a sibling file when a transfer passphrase is set.
+/// Returns the file to send, which is `zip_path` itself when encryption is off.
+fn seal_archive(app: &AppHandle, zip_path: &Path) -> Result<(PathBuf, Option<EncryptionParams>), AppError> {
+    match load_settings(app).transfer_passphrase {
+        Some(passphrase) => {
+            let sealed = zip_path.with_extension("zip.enc");
+            let params = encrypt_file(zip_path, &sealed, &passphrase)?;
+            Ok((sealed, Some(params)))
+        }
+        None => Ok((zip_path.to_path_buf(), None)),
+    }
+}
+
+fn send_zip_to(app: &AppHandle, zip_path: &Path, kind: &str, ip: &str, port: u16) -> Result<String, AppError> {
+    let (payload, encryption) = seal_archive(app, zip_path)?;
+    let size = fs::metadata(&payload)?.len();
+    let res = send_zip_to_inner(app, &payload, kind, ip, port, size, encryption);
+    if payload != zip_path { let _ = fs::remove_file(&payload); }
+    record_transfer(app, TransferRecord::new(TransferDirection::Sent, ip, kind, size).outcome(&res));
+    res
+}
+
+fn send_zip_to_inner(app: &AppHandle, zip_path: &Path, kind: &str, ip: &str, port: u16, size: u64, encryption: Option<EncryptionParams>) -> Result<String, AppError> {
+    let target: SocketAddr = format!("{}:{}", ip, port).parse::<SocketAddr>()?;
+    let mut stream = connect_with_retry(app, target, None)?;
+    let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: kind.into(), size, filename: zip_path.file_name().and_then(|s| s.to_str()).unwrap_or("notes.zip").into(), note_title: None, note_preview: None, notes_count: None, protocol: Some(PROTOCOL_V2.into()), sender_id: Some(device_id(app)), encryption };
+    let chunked = send_header_and_wait_ack(&mut stream, &header)?;
+    send_file(&mut stream, zip_path, chunked, &mut |_, _| {})?;
+    Ok(format!("Sent to {}", target))
+}
+
+/// Builds an outgoing archive at `tmp_zip` and passes it to `send`. The archive is
+/// removed however either step ends, including a build that fails halfway.
+fn with_outgoing_zip<T>(tmp_zip: PathBuf, build: impl FnOnce(&Path) -> Result<(), AppError>, send: impl FnOnce(&Path) -> Result<T, AppError>) -> Result<T, AppError> {
+    let _cleanup = ScratchGuard(tmp_zip.clone());
+    build(&tmp_zip)?;
+    send(&tmp_zip)
+}
+
+fn send_all_notes_to_blocking(app: AppHandle, ip: String, port: u16) -> Result<String, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    with_outgoing_zip(
+        scratch_path(&app, "outgoing_notes.zip")?,
+        |zip| zip_notes_dir(&notes_dir_path, zip).map(|_| ()),
+        |zip| send_zip_to(&app, zip, "all", &ip, port),
+    )
+}
+
+#[tauri::command]
+pub async fn send_all_notes_to(app: AppHandle, ip: String, port: u16) -> Result<String, AppError> {
+    run_blocking(move || send_all_notes_to_blocking(app, ip, port)).await
+}
+
+/// Outcome of sending to one peer as part of a multi-peer send.
+#[derive(Serialize)]
+pub struct PeerSendResult {
+    pub peer: PeerInfo,
+    pub ok: bool,
+    pub message: String,
+}
+
+/// Sends to every peer in turn; a peer that fails is reported and the rest are still tried.
+fn send_to_each(peers: Vec<PeerInfo>, mut send: impl FnMut(usize, &PeerInfo) -> Result<String, AppError>) -> Vec<PeerSendResult> {
+    let mut results = Vec::with_capacity(peers.len());
+    for (i, peer) in peers.into_iter().enumerate() {
+        let (ok, message) = match send(i, &peer) {
+            Ok(m) => (true, m),
+            Err(e) => (false, e.to_string()),
+        };
+        results.push(PeerSendResult { peer, ok, message });
+    }
+    results
+}
+
+fn send_all_notes_to_many_blocking(app: AppHandle, peers: Vec<PeerInfo>) -> Result<Vec<PeerSendResult>, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    let total = peers.len();
+    let results = with_outgoing_zip(
+        scratch_path(&app, "outgoing_notes.zip")?,
+        |zip| zip_notes_dir(&notes_dir_path, zip).map(|_| ()),
+        |zip| Ok(send_to_each(peers, |i, peer| {
+            let _ = app.emit("share://send_status", &serde_json::json!({"phase":"sending","peer":peer.name,"ip":peer.ip,"index":i,"count":total}));
+            send_zip_to(&app, zip, "all", &peer.ip, peer.port)
+        })),
+    )?;
+    let sent = results.iter().filter(|r| r.ok).count();
+    let _ = app.emit("share://send_done", &serde_json::json!({"ok":sent == total,"message":format!("Sent to {} of {} devices", sent, total),"results":results}));
+    Ok(results)
+}
+
+#[tauri::command]
+pub async fn send_all_notes_to_many(app: AppHandle, peers: Vec<PeerInfo>) -> Result<Vec<PeerSendResult>, AppError> {
+    run_blocking(move || send_all_notes_to_many_blocking(app, peers)).await
+}
+
+fn send_note_to_blocking(app: AppHandle, note_id: String, ip: String, port: u16) -> Result<String, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    with_outgoing_zip(
+        scratch_path(&app, "outgoing_single.zip")?,
+        |zip| zip_single_note(&notes_dir_path, &note_id, zip),
+        |zip| send_zip_to(&app, zip, "single", &ip, port),
+    )
+}
+
+#[tauri::command]
+pub async fn send_note_to(app: AppHandle, note_id: String, ip: String, port: u16) -> Result<String, AppError> {
+    run_blocking(move || send_note_to_blocking(app, note_id, ip, port)).await
+}
+
+fn send_selected_notes_to_blocking(app: AppHandle, ids: Vec<String>, ip: String, port: u16) -> Result<String, AppError> {
+    let notes_dir_path = notes_dir(&app)?;
+    with_outgoing_zip(
+        scratch_path(&app, "outgoing_selected.zip")?,
+        |zip| zip_selected_notes(&notes_dir_path, &ids, zip).map(|_| ()),
+        |zip| send_zip_to(&app, zip, "selected", &ip, port),
+    )
+}
+
+#[tauri::command]
+pub async fn send_selected_notes_to(app: AppHandle, ids: Vec<String>, ip: String, port: u16) -> Result<String, AppError> {
+    run_blocking(move || send_selected_notes_to_blocking(app, ids, ip, port)).await
+}
+
+/// Connects to the receiver, performs the header handshake and streams the
+/// archive while emitting `share://send_status` progress.
+fn send_zip_with_progress(app: &AppHandle, transfer_id: &str, zip_path: &Path, ip: &str, port: u16, header: &TransferHeader) -> Result<(), AppError> {
+    let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"connecting","bytes":header.size}), Some(transfer_id)));
+    let target: SocketAddr = format!("{}:{}", ip, port).parse::<SocketAddr>()?;
+    let mut stream = connect_with_retry(app, target, Some(transfer_id))?;
+    let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"handshake"}), Some(transfer_id)));
+    let chunked = send_header_and_wait_ack(&mut stream, header)?;
+    let mut meter = RateMeter::new();
+    send_file(&mut stream, zip_path, chunked, &mut |sent, total| {
+        let (rate, eta) = meter.record(sent, total);
+        let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"sending","sent":sent,"total":total,"bytesPerSec":rate,"etaSecs":eta}), Some(transfer_id)));
+    })
+}
+
+/// Sends a prepared archive in the background and always removes it afterwards.
+fn spawn_send(app: AppHandle, transfer_id: String, zip_path: PathBuf, ip: String, port: u16, mut header: TransferHeader) {
+    std::thread::spawn(move || {
+        let res = seal_archive(&app, &zip_path).and_then(|(payload, encryption)| {
+            header.size = fs::metadata(&payload)?.len();
+            header.encryption = encryption;
+            let res = send_zip_with_progress(&app, &transfer_id, &payload, &ip, port, &header);
+            if payload != zip_path { let _ = fs::remove_file(&payload); }
+            res
+        });
+        record_transfer(&app, TransferRecord::new(TransferDirection::Sent, ip.as_str(), header.kind.as_str(), header.size).notes(header.notes_count).outcome(&res));
+        let _ = app.emit("share://send_done", &send_done_payload(&transfer_id, &res));
+        let _ = fs::remove_file(&zip_path);
+    });
+}
+
+fn emit_send_failed(app: &AppHandle, transfer_id: &str, err: AppError) {
+    let _ = app.emit("share://send_done", &send_done_payload(transfer_id, &Err(err)));
+}
+
+/// The final `share://send_done` of a background send.
+fn send_done_payload(transfer_id: &str, result: &Result<(), AppError>) -> serde_json::Value {
+    let done = match result {
+        Ok(()) => serde_json::json!({"ok":true,"message":"Sent"}),
+        Err(e) => serde_json::json!({"ok":false,"message":e.to_string()}),
+    };
+    with_transfer_id(done, Some(transfer_id))
+}
+
+/// Short id naming one background send, e.g. `send-1a2b3c4d`. Every
+/// `share://send_status` and the final `share://send_done` carry it as `transferId`.
+fn new_transfer_id() -> String {
+    format!("send-{}", &Uuid::new_v4().simple().to_string()[..8])
+}
+
+/// Adds `transferId` to an event payload when the send has one.
+fn with_transfer_id(mut payload: serde_json::Value, transfer_id: Option<&str>) -> serde_json::Value {
+    if let (Some(id), Some(map)) = (transfer_id, payload.as_object_mut()) {
+        map.insert("transferId".into(), id.into());
+    }
+    payload
+}
+
+/// The `start_send_*` commands return at once with the id their events will carry.
+#[tauri::command]
+pub fn start_send_all_notes_to(app: AppHandle, ip: String, port: u16) -> Result<String, AppError> {
+    let transfer_id = new_transfer_id();
+    let id = transfer_id.clone();
+    std::thread::spawn(move || {
+        let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"preparing"}), Some(&id)));
+        let notes_dir_path = match notes_dir(&app) { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        let tmp_zip = match scratch_path(&app, "outgoing_notes.zip") { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        if let Err(e) = zip_notes_dir(&notes_dir_path, &tmp_zip) { emit_send_failed(&app, &id, e); return; }
+        let size = fs::metadata(&tmp_zip).map(|m| m.len()).unwrap_or(0);
+        // count notes
+        let idx_count = load_index_from(&notes_dir_path).ok().map(|v| v.len() as u32);
+        let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "all".into(), size, filename: "outgoing_notes.zip".into(), note_title: None, note_preview: None, notes_count: idx_count, protocol: Some(PROTOCOL_V2.into()), sender_id: Some(device_id(&app)), encryption: None };
+        spawn_send(app, id, tmp_zip, ip, port, header);
+    });
+    Ok(transfer_id)
+}
+
 #[tauri::command]
-pub fn start_send_note_to(app: AppHandle, note_id: String, ip: String, port: u16) -> Result<(), String> {
-    let app_clone = app.clone();
+pub fn start_send_note_to(app: AppHandle, note_id: String, ip: String, port: u16) -> Result<String, AppError> {
+    let transfer_id = new_transfer_id();
+    let id = transfer_id.clone();
     std::thread::spawn(move || {
-        let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"preparing"}));
-        let notes_dir_path = match notes_dir(&app_clone) { Ok(p)=>p, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); return; } };
-        let tmp_zip = notes_dir_path.join("outgoing_single.zip");
-        if let Err(e) = zip_single_note(&notes_dir_path, &note_id, &tmp_zip) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); return; }
-        let size = fs::metadata(&tmp_zip).ok().and_then(|m| Some(m.len())).unwrap_or(0);
+        let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"preparing"}), Some(&id)));
+        let notes_dir_path = match notes_dir(&app) { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        let tmp_zip = match scratch_path(&app, "outgoing_single.zip") { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        if let Err(e) = zip_single_note(&notes_dir_path, &note_id, &tmp_zip) { emit_send_failed(&app, &id, e); return; }
+        let size = fs::metadata(&tmp_zip).map(|m| m.len()).unwrap_or(0);
         // load meta and preview
-        let title = fs::read_to_string(notes_dir_path.join("index.json")).ok()
-            .and_then(|s| serde_json::from_str::<Vec<StoredNoteMetadata>>(&s).ok())
+        let title = load_index_from(&notes_dir_path).ok()
             .and_then(|v| v.into_iter().find(|m| m.id==note_id).map(|m| m.title));
-        let preview = fs::read_to_string(notes_dir_path.join(format!("{}.md", note_id))).ok().map(|c| preview_from_content(&c));
-        let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"connecting","bytes":size}));
-        match TcpStream::connect(format!("{}:{}", ip, port)) {
-            Ok(mut stream) => {
-                let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"handshake"}));
-                let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "single".into(), size, filename: "outgoing_single.zip".into(), note_title: title, note_preview: preview, notes_count: None };
-                if let Err(e) = send_header_and_wait_ack(&mut stream, &header) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e})); let _=fs::remove_file(&tmp_zip); return; }
-                if write_u64_be(&mut stream, size).is_err() { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":"Failed to write size"})); let _=fs::remove_file(&tmp_zip); return; }
-                let mut f = match fs::File::open(&tmp_zip){ Ok(f)=>f, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; } };
-                let mut buf = [0u8; 8192];
-                let mut sent: u64 = 0;
-                loop {
-                    let n = match f.read(&mut buf) { Ok(n)=>n, Err(e)=>{ let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; } };
-                    if n==0 { break; }
-                    if let Err(e) = stream.write_all(&buf[..n]) { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); let _=fs::remove_file(&tmp_zip); return; }
-                    sent += n as u64;
-                    let _ = app_clone.emit("share://send_status", &serde_json::json!({"phase":"sending","sent":sent,"total":size}));
-                }
-                let _ = app_clone.emit("share://send_done", &serde_json::json!({"ok":true,"message":"Sent"}));
+        let preview = fs::read_to_string(note_file(&notes_dir_path, &note_id)).ok().map(|c| preview_from_content(&c));
+        let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "single".into(), size, filename: "outgoing_single.zip".into(), note_title: title, note_preview: preview, notes_count: None, protocol: Some(PROTOCOL_V2.into()), sender_id: Some(device_id(&app)), encryption: None };
+        spawn_send(app, id, tmp_zip, ip, port, header);
+    });
+    Ok(transfer_id)
+}
+
+#[tauri::command]
+pub fn start_send_selected_notes_to(app: AppHandle, ids: Vec<String>, ip: String, port: u16) -> Result<String, AppError> {
+    let transfer_id = new_transfer_id();
+    let id = transfer_id.clone();
+    std::thread::spawn(move || {
+        let _ = app.emit("share://send_status", &with_transfer_id(serde_json::json!({"phase":"preparing"}), Some(&id)));
+        let notes_dir_path = match notes_dir(&app) { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        let tmp_zip = match scratch_path(&app, "outgoing_selected.zip") { Ok(p)=>p, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        let count = match zip_selected_notes(&notes_dir_path, &ids, &tmp_zip) { Ok(n)=>n, Err(e)=>{ emit_send_failed(&app, &id, e); return; } };
+        let size = fs::metadata(&tmp_zip).map(|m| m.len()).unwrap_or(0);
+        let header = TransferHeader { magic: TRANSFER_MAGIC.into(), kind: "selected".into(), size, filename: "outgoing_selected.zip".into(), note_title: None, note_preview: None, notes_count: Some(count), protocol: Some(PROTOCOL_V2.into()), sender_id: Some(device_id(&app)), encryption: None };
+        spawn_send(app, id, tmp_zip, ip, port, header);
+    });
+    Ok(transfer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread::JoinHandle;
+
+    fn pending(age: Duration) -> PendingTransfer {
+        PendingTransfer {
+            stream: None,
+            header: TransferHeader {
+                magic: TRANSFER_MAGIC.into(),
+                kind: "all".into(),
+                size: 0,
+                filename: "notes.zip".into(),
+                note_title: None,
+                note_preview: None,
+                notes_count: None,
+                protocol: None,
+                sender_id: None,
+                encryption: None,
+            },
+            peer: SocketAddr::from((Ipv4Addr::LOCALHOST, TRANSFER_PORT)),
+            received_at: Instant::now().checked_sub(age).unwrap(),
+        }
+    }
+
+    #[test]
+    fn take_expired_removes_only_old_offers() {
+        let mut map = HashMap::from([("old".to_string(), pending(Duration::from_secs(5))), ("new".to_string(), pending(Duration::ZERO))]);
+        let expired: Vec<String> = take_expired(&mut map, Duration::from_secs(1)).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(expired, ["old"]);
+        assert!(map.contains_key("new") && map.len() == 1);
+    }
+
+    fn temp_dir() -> ScratchGuard {
+        let dir = std::env::temp_dir().join(format!("notetake-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        ScratchGuard(dir)
+    }
+
+    fn meta(id: &str, updated_at: &str) -> StoredNoteMetadata {
+        serde_json::from_value(serde_json::json!({ "id": id, "title": id, "updatedAt": updated_at })).unwrap()
+    }
+
+    /// A notes folder holding `note` with `content` and an index listing it.
+    fn notes_folder(note: StoredNoteMetadata, content: &str) -> ScratchGuard {
+        let dir = temp_dir();
+        fs::write(dir.0.join(format!("{}.md", note.id)), content).unwrap();
+        fs::write(dir.0.join("index.json"), index_json(&[note]).unwrap()).unwrap();
+        dir
+    }
+
+    /// Merges an incoming copy of note `a` that differs from the local one.
+    fn merge_conflict(local_at: &str, incoming_at: &str, strategy: MergeStrategy) -> (ScratchGuard, MergeOutcome) {
+        let dest = notes_folder(meta("a", local_at), "local");
+        let incoming = notes_folder(meta("a", incoming_at), "theirs");
+        let outcome = merge_index(&dest.0, &incoming.0, &[meta("a", incoming_at)], strategy, Duration::ZERO, "peer").unwrap();
+        (dest, outcome)
+    }
+
+    #[test]
+    fn merge_takes_a_newer_incoming_note_even_when_keeping_both() {
+        let (dest, outcome) = merge_conflict("2024-01-01T00:00:00Z", "2024-01-02T00:00:00Z", MergeStrategy::KeepBoth);
+        assert_eq!(outcome.updated, ["a"]);
+        assert!(outcome.kept_both.is_empty());
+        assert_eq!(parse_index(&fs::read_to_string(dest.0.join("index.json")).unwrap()).unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn merge_skips_an_older_incoming_note() {
+        let (_dest, outcome) = merge_conflict("2024-01-02T00:00:00Z", "2024-01-01T00:00:00Z", MergeStrategy::Newest);
+        assert!(outcome.updated.is_empty());
+        assert_eq!(outcome.skipped, 1);
+    }
+
+    #[test]
+    fn merge_preserves_a_newer_local_note_file() {
+        for strategy in [MergeStrategy::Newest, MergeStrategy::KeepBoth] {
+            let (dest, _) = merge_conflict("2024-01-02T00:00:00Z", "2024-01-01T00:00:00Z", strategy);
+            assert_eq!(fs::read_to_string(note_file(&dest.0, "a")).unwrap(), "local");
+            let index = parse_index(&fs::read_to_string(dest.0.join("index.json")).unwrap()).unwrap().0;
+            assert_eq!(index.len(), 1);
+            assert_eq!(index[0].updated_at, "2024-01-02T00:00:00Z");
+        }
+    }
+
+    #[test]
+    fn merge_keeps_both_on_a_tie_and_copies_attachments() {
+        let at = "2024-01-01T00:00:00Z";
+        let dest = notes_folder(meta("a", at), "local");
+        let incoming = notes_folder(meta("a", at), "![pic](attachments/a/pic.png)");
+        fs::create_dir_all(attachment_dir(&incoming.0, "a")).unwrap();
+        fs::write(attachment_dir(&incoming.0, "a").join("pic.png"), b"png").unwrap();
+
+        let outcome = merge_index(&dest.0, &incoming.0, &[meta("a", at)], MergeStrategy::KeepBoth, Duration::ZERO, "peer").unwrap();
+        let [copy_id] = outcome.kept_both.as_slice() else { panic!("expected one copy, got {:?}", outcome.kept_both) };
+        let content = fs::read_to_string(note_file(&dest.0, copy_id)).unwrap();
+        assert_eq!(content, format!("![pic](attachments/{copy_id}/pic.png)"));
+        assert_eq!(fs::read(attachment_dir(&dest.0, copy_id).join("pic.png")).unwrap(), b"png");
+        assert_eq!(fs::read_to_string(note_file(&dest.0, "a")).unwrap(), "local");
+    }
+
+    #[test]
+    fn merge_adds_new_notes_and_skips_unsafe_ids() {
+        let dest = temp_dir();
+        let incoming = temp_dir();
+        let notes = [meta("b", "2024-01-01T00:00:00Z"), meta("../x", "2024-01-01T00:00:00Z")];
+        let outcome = merge_index(&dest.0, &incoming.0, &notes, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert_eq!(outcome.added, ["b"]);
+        assert_eq!(outcome.skipped, 1);
+        assert_eq!(outcome.received, 2);
+    }
+
+    /// Runs `send` on the other end of a loopback connection.
+    fn connected<T: Send + 'static>(send: impl FnOnce(TcpStream) -> T + Send + 'static) -> (TcpStream, JoinHandle<T>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sender = std::thread::spawn(move || send(TcpStream::connect(addr).unwrap()));
+        (listener.accept().unwrap().0, sender)
+    }
+
+    #[test]
+    fn recv_file_writes_the_announced_bytes() {
+        let dir = temp_dir();
+        let out = dir.0.join("in.zip");
+        let (mut stream, sender) = connected(|mut s| {
+            s.write_all(&5u64.to_be_bytes()).unwrap();
+            s.write_all(b"hello").unwrap();
+        });
+        let mut progress = Vec::new();
+        recv_file(&mut stream, &out, 5, false, &mut |received, total| progress.push((received, total))).unwrap();
+        sender.join().unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"hello");
+        assert_eq!(progress.last(), Some(&(5, 5)));
+    }
+
+    #[test]
+    fn recv_file_fails_when_the_sender_stops_early() {
+        let dir = temp_dir();
+        let (mut stream, _sender) = connected(|mut s| {
+            s.write_all(&10u64.to_be_bytes()).unwrap();
+            s.write_all(b"abc").unwrap();
+        });
+        let result = recv_file(&mut stream, &dir.0.join("in.zip"), 10, false, &mut |_, _| {});
+        assert!(matches!(result, Err(AppError::Network(message)) if message.contains("3 of 10")));
+    }
+
+    #[test]
+    fn recv_file_rejects_more_than_the_offer_declared() {
+        let dir = temp_dir();
+        let (mut stream, _sender) = connected(|mut s| s.write_all(&10u64.to_be_bytes()).unwrap());
+        let result = recv_file(&mut stream, &dir.0.join("in.zip"), 5, false, &mut |_, _| {});
+        assert!(matches!(result, Err(AppError::Network(_))));
+    }
+
+    #[test]
+    fn reachable_ipv4s_skip_loopback_and_link_local() {
+        let v4 = |name: &str, ip: [u8; 4]| Interface {
+            name: name.into(),
+            addr: IfAddr::V4(Ifv4Addr { ip: ip.into(), netmask: Ipv4Addr::new(255, 255, 0, 0), broadcast: None }),
+            index: None,
+        };
+        let v6 = Interface {
+            name: "eth0".into(),
+            addr: IfAddr::V6(if_addrs::Ifv6Addr { ip: "fe80::1".parse().unwrap(), netmask: "ffff::".parse().unwrap(), broadcast: None }),
+            index: None,
+        };
+        let ifaces = vec![v4("lo", [127, 0, 0, 1]), v4("eth0", [169, 254, 3, 7]), v6, v4("wlan0", [192, 168, 1, 5])];
+        let names: Vec<(String, Ipv4Addr)> = reachable_ipv4s(ifaces).map(|(name, v4)| (name, v4.ip)).collect();
+        assert_eq!(names, [("wlan0".to_string(), Ipv4Addr::new(192, 168, 1, 5))]);
+    }
+
+    #[test]
+    fn chosen_name_ignores_blank_names() {
+        let named = |name: Option<&str>| Settings { device_name: name.map(str::to_string), ..Settings::default() };
+        assert_eq!(chosen_name(&named(Some("  Desk  "))).as_deref(), Some("Desk"));
+        assert_eq!(chosen_name(&named(Some("   "))), None);
+        assert_eq!(chosen_name(&named(None)), None);
+    }
+
+    #[test]
+    fn ping_carries_the_device_name_and_id() {
+        let ping: serde_json::Value = serde_json::from_slice(&ping_bytes("Desk", "dev-1").unwrap()).unwrap();
+        assert_eq!(ping["magic"], DISCOVERY_MAGIC);
+        assert_eq!(ping["kind"], "ping");
+        assert_eq!(ping["name"], "Desk");
+        assert_eq!(ping["id"], "dev-1");
+        assert_eq!(ping["transfer_port"], transfer_port());
+        assert!(ping.get("note_count").is_none());
+    }
+
+    #[test]
+    fn pong_advertises_the_note_count() {
+        let dir = library(&[("a", "A"), ("b", "B"), ("c", "C")]);
+        let pong: DiscoveryPing = serde_json::from_slice(&pong_bytes("Desk", "dev-1", 4000, note_count(&dir.0)).unwrap()).unwrap();
+        assert_eq!(pong.kind, "pong");
+        assert_eq!(pong.transfer_port, 4000);
+        assert_eq!(pong.note_count, Some(3));
+
+        // Older peers leave it out.
+        let legacy = serde_json::json!({ "magic": DISCOVERY_MAGIC, "kind": "pong", "name": "Old", "transfer_port": 4000, "id": "x" });
+        assert_eq!(serde_json::from_value::<DiscoveryPing>(legacy).unwrap().note_count, None);
+    }
+
+    #[test]
+    fn send_to_each_keeps_going_after_a_failed_peer() {
+        let peer = |name: &str| PeerInfo { name: name.into(), ip: "10.0.0.1".into(), port: TRANSFER_PORT, id: name.into(), note_count: None };
+        let mut tried = Vec::new();
+        let results = send_to_each(vec![peer("a"), peer("b"), peer("c")], |i, peer| {
+            tried.push(i);
+            if peer.name == "b" { Err(AppError::Network("refused".into())) } else { Ok(format!("Sent to {}", peer.name)) }
+        });
+        assert_eq!(tried, [0, 1, 2]);
+        let summary: Vec<(&str, bool, &str)> = results.iter().map(|r| (r.peer.name.as_str(), r.ok, r.message.as_str())).collect();
+        assert_eq!(summary, [("a", true, "Sent to a"), ("b", false, "refused"), ("c", true, "Sent to c")]);
+    }
+
+    /// A notes folder holding a note per `(id, content)` pair, all in the index.
+    fn library(notes: &[(&str, &str)]) -> ScratchGuard {
+        let dir = temp_dir();
+        for (id, content) in notes {
+            fs::write(note_file(&dir.0, id), content).unwrap();
+        }
+        let index: Vec<StoredNoteMetadata> = notes.iter().map(|(id, _)| meta(id, "2024-01-01T00:00:00Z")).collect();
+        fs::write(dir.0.join("index.json"), index_json(&index).unwrap()).unwrap();
+        dir
+    }
+
+    /// Entry names of the archive at `path`, in order.
+    fn zip_entries(path: &Path) -> Vec<String> {
+        let archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        archive.file_names().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn zip_selected_notes_archives_only_the_chosen_notes() {
+        let dir = library(&[("a", "A"), ("b", "B"), ("c", "C")]);
+        let out = temp_dir();
+        let zip_path = out.0.join("selected.zip");
+        let count = zip_selected_notes(&dir.0, &["c".into(), "a".into(), "gone".into()], &zip_path).unwrap();
+        assert_eq!(count, 2);
+        let mut entries = zip_entries(&zip_path);
+        entries.sort();
+        assert_eq!(entries, ["a.md", "c.md", "index.json"]);
+        let extracted = out.0.join("extracted");
+        unzip_into(&extracted, &zip_path).unwrap();
+        let ids: Vec<String> = load_index_from(&extracted).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["a", "c"]);
+    }
+
+    #[test]
+    fn zip_selected_notes_needs_at_least_one_note() {
+        let dir = library(&[("a", "A")]);
+        let result = zip_selected_notes(&dir.0, &["gone".into()], &dir.0.join("selected.zip"));
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn zip_notes_dir_archives_notes_index_and_attachments() {
+        let dir = library(&[("a", "A"), ("b", "B")]);
+        fs::create_dir_all(attachment_dir(&dir.0, "a")).unwrap();
+        fs::write(attachment_dir(&dir.0, "a").join("pic.png"), b"png").unwrap();
+        let out = temp_dir();
+        let zip_path = out.0.join("backup.zip");
+        let archived = zip_notes_dir(&dir.0, &zip_path).unwrap();
+        assert_eq!(archived.notes, 2);
+        assert!(archived.skipped.is_empty());
+        let mut entries = zip_entries(&zip_path);
+        entries.sort();
+        assert_eq!(entries, ["a.md", "attachments/a/pic.png", "b.md", "index.json"]);
+    }
+
+    #[test]
+    fn export_target_adds_a_missing_extension() {
+        assert_eq!(export_target("/tmp/notes"), PathBuf::from("/tmp/notes.zip"));
+        assert_eq!(export_target("/tmp/notes.backup"), PathBuf::from("/tmp/notes.backup"));
+    }
+
+    #[tokio::test]
+    async fn run_blocking_works_off_the_calling_thread() {
+        let caller = std::thread::current().id();
+        let worker = run_blocking(|| Ok(std::thread::current().id())).await.unwrap();
+        assert_ne!(worker, caller);
+        let failed: Result<(), AppError> = run_blocking(|| Err(AppError::Network("down".into()))).await;
+        assert!(matches!(failed, Err(AppError::Network(m)) if m == "down"));
+    }
+
+    #[test]
+    fn recv_file_throttles_progress_but_reports_completion() {
+        let dir = temp_dir();
+        let size = 4 * TRANSFER_BUFFER_SIZE as u64;
+        let (mut stream, sender) = connected(move |mut s| {
+            s.write_all(&size.to_be_bytes()).unwrap();
+            s.write_all(&vec![7u8; size as usize]).unwrap();
+        });
+        let mut progress = Vec::new();
+        recv_file(&mut stream, &dir.0.join("in.zip"), size, false, &mut |received, total| progress.push((received, total))).unwrap();
+        sender.join().unwrap();
+        // A loopback transfer this small finishes inside one interval, so only the final report goes out.
+        assert_eq!(progress, [(size, size)]);
+    }
+
+    #[test]
+    fn recv_file_acknowledges_every_chunk() {
+        let dir = temp_dir();
+        let out = dir.0.join("in.zip");
+        let (mut stream, sender) = connected(|mut s| {
+            s.write_all(&6u64.to_be_bytes()).unwrap();
+            let mut acks = Vec::new();
+            for chunk in [b"abc", b"def"] {
+                s.write_all(&3u32.to_be_bytes()).unwrap();
+                s.write_all(chunk).unwrap();
+                let mut ack = [0u8; 1];
+                s.read_exact(&mut ack).unwrap();
+                acks.push(ack);
             }
-            Err(e) => { let _=app_clone.emit("share://send_done", &serde_json::json!({"ok":false,"message":e.to_string()})); }
+            acks
+        });
+        recv_file(&mut stream, &out, 6, true, &mut |_, _| {}).unwrap();
+        assert_eq!(sender.join().unwrap(), [*CHUNK_ACK, *CHUNK_ACK]);
+        assert_eq!(fs::read(&out).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn with_backoff_retries_with_growing_delays() {
+        let mut failures = 2;
+        let mut waits = Vec::new();
+        let connected = with_backoff(
+            || if failures > 0 { failures -= 1; Err(std::io::ErrorKind::ConnectionRefused.into()) } else { Ok("stream") },
+            |attempt, delay| waits.push((attempt, delay)),
+        );
+        assert_eq!(connected.unwrap(), "stream");
+        assert_eq!(waits, [(1, CONNECT_BACKOFF_START), (2, CONNECT_BACKOFF_START * 2)]);
+    }
+
+    #[test]
+    fn with_backoff_gives_up_after_the_last_attempt() {
+        let mut attempts = 0;
+        let mut waits = 0;
+        let result: Result<(), AppError> = with_backoff(
+            || { attempts += 1; Err(std::io::ErrorKind::ConnectionRefused.into()) },
+            |_, _| waits += 1,
+        );
+        assert!(matches!(result, Err(AppError::Network(_))));
+        assert_eq!((attempts, waits), (CONNECT_ATTEMPTS, CONNECT_ATTEMPTS - 1));
+    }
+
+    #[test]
+    fn send_file_streams_a_large_archive_in_both_modes() {
+        let dir = temp_dir();
+        let source = dir.0.join("out.zip");
+        // Several buffers' worth, and not a multiple of either buffer size.
+        let data: Vec<u8> = (0..5 * 1024 * 1024 + 123).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        for chunked in [false, true] {
+            let dest = dir.0.join(format!("in-{chunked}.zip"));
+            let source = source.clone();
+            let (mut stream, sender) = connected(move |mut s| send_file(&mut s, &source, chunked, &mut |_, _| {}));
+            recv_file(&mut stream, &dest, data.len() as u64, chunked, &mut |_, _| {}).unwrap();
+            sender.join().unwrap().unwrap();
+            assert!(fs::read(&dest).unwrap() == data, "chunked: {chunked}");
         }
-        let _ = fs::remove_file(&tmp_zip);
-    });
-    Ok(())
+    }
+
+    fn offer(sender_id: Option<&str>, encrypted: bool) -> TransferHeader {
+        let mut header = pending(Duration::ZERO).header;
+        header.size = 10;
+        header.sender_id = sender_id.map(str::to_string);
+        header.encryption = encrypted.then(|| serde_json::from_value(serde_json::json!({ "salt": "", "nonce": "" })).unwrap());
+        header
+    }
+
+    fn screen(header: &TransferHeader, settings: &Settings) -> OfferDecision {
+        screen_offer(header, SocketAddr::from((Ipv4Addr::LOCALHOST, TRANSFER_PORT)), settings, 1024, None, false)
+    }
+
+    #[test]
+    fn only_encrypted_offers_from_trusted_ids_are_trusted() {
+        let settings = Settings { trusted_peers: vec!["friend".into()], transfer_passphrase: Some("secret".into()), ..Settings::default() };
+        assert_eq!(screen(&offer(Some("friend"), true), &settings), OfferDecision::Offer { trusted: true });
+        assert_eq!(screen(&offer(Some("stranger"), true), &settings), OfferDecision::Offer { trusted: false });
+        assert_eq!(screen(&offer(None, true), &settings), OfferDecision::Offer { trusted: false });
+
+        let plain = Settings { trusted_peers: vec!["friend".into()], ..Settings::default() };
+        assert_eq!(screen(&offer(Some("friend"), false), &plain), OfferDecision::Offer { trusted: false });
+    }
+
+    #[test]
+    fn rejecting_unknown_peers_explains_an_unencrypted_trusted_offer() {
+        let settings = Settings { trusted_peers: vec!["friend".into()], reject_unknown_peers: true, ..Settings::default() };
+        let OfferDecision::Reject(message) = screen(&offer(Some("friend"), false), &settings) else { panic!("expected a rejection") };
+        assert!(message.contains("must encrypt"), "{message}");
+    }
+
+    #[test]
+    fn rejecting_unknown_peers_refuses_only_untrusted_offers() {
+        let settings = Settings {
+            trusted_peers: vec!["friend".into()],
+            reject_unknown_peers: true,
+            transfer_passphrase: Some("secret".into()),
+            ..Settings::default()
+        };
+        assert_eq!(screen(&offer(Some("friend"), true), &settings), OfferDecision::Offer { trusted: true });
+        for header in [offer(Some("stranger"), true), offer(None, true)] {
+            assert!(matches!(screen(&header, &settings), OfferDecision::Reject(m) if m.contains("untrusted device")));
+        }
+        let asking = Settings { reject_unknown_peers: false, ..settings };
+        assert_eq!(screen(&offer(Some("stranger"), true), &asking), OfferDecision::Offer { trusted: false });
+    }
+
+    #[test]
+    fn chosen_source_must_belong_to_an_interface() {
+        let interfaces = [(Ipv4Addr::new(192, 168, 1, 5), Ipv4Addr::new(255, 255, 255, 0))];
+        assert_eq!(chosen_source(Some(" 192.168.1.5 "), &interfaces).unwrap().as_deref(), Some("192.168.1.5"));
+        assert_eq!(chosen_source(Some("  "), &interfaces).unwrap(), None);
+        assert_eq!(chosen_source(None, &interfaces).unwrap(), None);
+        assert!(matches!(chosen_source(Some("10.0.0.1"), &interfaces), Err(AppError::InvalidInput(_))));
+        assert!(matches!(chosen_source(Some("eth0"), &interfaces), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn connect_from_leaves_through_the_source_address() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = connect_from(Some(Ipv4Addr::LOCALHOST), listener.local_addr().unwrap()).unwrap();
+        let (accepted, from) = listener.accept().unwrap();
+        assert_eq!(from.ip(), Ipv4Addr::LOCALHOST);
+        assert_eq!(stream.local_addr().unwrap(), accepted.peer_addr().unwrap());
+    }
+
+    #[test]
+    fn bind_with_fallback_picks_another_port_when_taken() {
+        let taken = TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let fallback = bind_with_fallback(port).unwrap();
+        let fallback_port = fallback.local_addr().unwrap().port();
+        assert_ne!(fallback_port, port);
+        assert_ne!(fallback_port, 0);
+    }
+
+    #[test]
+    fn is_newer_compares_instants_not_strings() {
+        // 11:00+02:00 is 09:00 UTC, earlier than 10:00Z even though it sorts later.
+        assert!(!is_newer("2024-03-01T11:00:00+02:00", "2024-03-01T10:00:00Z"));
+        assert!(is_newer("2024-03-01T10:00:00Z", "2024-03-01T11:00:00+02:00"));
+        assert!(is_newer("2024-03-01 10:00:01", "2024-03-01T10:00:00Z"));
+        assert!(!is_newer("2024-03-01T10:00:00Z", "2024-03-01T12:00:00+02:00"));
+    }
+
+    #[test]
+    fn is_newer_prefers_parsable_timestamps() {
+        assert!(is_newer("2024-03-01T10:00:00Z", "garbage"));
+        assert!(!is_newer("garbage", "2024-03-01T10:00:00Z"));
+        assert!(is_newer("b", "a"));
+        assert!(!is_newer("a", "a"));
+    }
+
+    #[test]
+    fn zip_notes_dir_never_archives_zips() {
+        let dir = library(&[("a", "A")]);
+        fs::write(dir.0.join("outgoing_notes.zip"), b"leftover").unwrap();
+        // Even an archive written into the folder it is reading stays out of itself.
+        let zip_path = dir.0.join("in_progress.zip");
+        zip_notes_dir(&dir.0, &zip_path).unwrap();
+        let entries = zip_entries(&zip_path);
+        assert!(entries.iter().all(|name| !name.ends_with(".zip")), "{entries:?}");
+        assert!(entries.contains(&"a.md".to_string()));
+    }
+
+    #[test]
+    fn send_events_carry_the_transfer_id() {
+        let id = new_transfer_id();
+        assert!(id.starts_with("send-") && id.len() == 13, "{id}");
+        assert_ne!(id, new_transfer_id());
+
+        let status = with_transfer_id(serde_json::json!({"phase":"preparing"}), Some(&id));
+        assert_eq!(status, serde_json::json!({"phase":"preparing","transferId":id}));
+        assert_eq!(send_done_payload(&id, &Ok(())), serde_json::json!({"transferId":id,"ok":true,"message":"Sent"}));
+        let failed = send_done_payload(&id, &Err(AppError::Network("refused".into())));
+        assert_eq!((failed["transferId"].as_str(), failed["ok"].as_bool()), (Some(id.as_str()), Some(false)));
+        // Sends without an id, like the blocking ones, leave the payload as it is.
+        assert_eq!(with_transfer_id(serde_json::json!({"phase":"retrying"}), None), serde_json::json!({"phase":"retrying"}));
+    }
+
+    #[test]
+    fn legacy_scratch_cleanup_leaves_notes_alone() {
+        let dir = library(&[("a", "A")]);
+        for name in LEGACY_SCRATCH {
+            if name.ends_with("_tmp") {
+                fs::create_dir_all(dir.0.join(name).join("nested")).unwrap();
+            } else {
+                fs::write(dir.0.join(name), b"partial").unwrap();
+            }
+        }
+        fs::write(dir.0.join("keep.zip"), b"user file").unwrap();
+
+        remove_legacy_scratch(&dir.0);
+        let mut left: Vec<String> = fs::read_dir(&dir.0).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        left.sort();
+        assert_eq!(left, ["a.md", "index.json", "keep.zip"]);
+    }
+
+    fn peer_at(id: &str, ip: &str, port: u16) -> PeerInfo {
+        PeerInfo { name: format!("{id} device"), ip: ip.into(), port, id: id.into(), note_count: None }
+    }
+
+    #[test]
+    fn favorites_are_added_once_per_id() {
+        let mut favorites = Vec::new();
+        add_favorite(&mut favorites, peer_at("a", "10.0.0.1", 4000)).unwrap();
+        add_favorite(&mut favorites, peer_at("b", "10.0.0.2", 4000)).unwrap();
+        add_favorite(&mut favorites, peer_at("a", "10.0.0.9", 4001)).unwrap();
+        let entries: Vec<(&str, &str, u16)> = favorites.iter().map(|f| (f.id.as_str(), f.ip.as_str(), f.port)).collect();
+        assert_eq!(entries, [("a", "10.0.0.9", 4001), ("b", "10.0.0.2", 4000)]);
+        assert!(matches!(add_favorite(&mut favorites, peer_at(" ", "10.0.0.3", 4000)), Err(AppError::InvalidInput(_))));
+
+        favorites.retain(|f| f.id != "a");
+        assert_eq!(favorites.len(), 1);
+        assert_eq!(favorites[0].id, "b");
+    }
+
+    #[test]
+    fn favorites_follow_their_id_to_a_new_address() {
+        let mut favorite = FavoritePeer { name: "Desk".into(), id: "a".into(), ip: "10.0.0.1".into(), port: 4000 };
+        let scan = [peer_at("b", "10.0.0.1", 4000), peer_at("a", "10.0.0.7", 4002)];
+        assert!(refresh_favorite(&mut favorite, &scan));
+        assert_eq!((favorite.ip.as_str(), favorite.port), ("10.0.0.7", 4002));
+        assert!(!refresh_favorite(&mut favorite, &scan));
+        // Not found in the scan: the stored address is still the best guess.
+        assert!(!refresh_favorite(&mut favorite, &[peer_at("b", "10.0.0.8", 4000)]));
+        assert_eq!(favorite.ip, "10.0.0.7");
+    }
+
+    #[test]
+    fn offers_past_the_pending_cap_are_rejected() {
+        let max_age = Duration::from_secs(60);
+        let mut map: HashMap<String, PendingTransfer> = HashMap::new();
+        map.insert("fresh".into(), pending(Duration::ZERO));
+        map.insert("stale".into(), pending(Duration::from_secs(120)));
+        assert!(at_capacity(&map, 2));
+        let decision = screen_offer(&offer(None, false), SocketAddr::from((Ipv4Addr::LOCALHOST, TRANSFER_PORT)), &Settings::default(), 1024, None, at_capacity(&map, 2));
+        assert!(matches!(decision, OfferDecision::Reject(m) if m.ends_with("too many pending transfers")));
+
+        // Sweeping the expired offer frees its slot.
+        take_expired(&mut map, max_age);
+        assert!(!at_capacity(&map, 2));
+        assert_eq!(screen(&offer(None, false), &Settings::default()), OfferDecision::Offer { trusted: false });
+    }
+
+    #[test]
+    fn bytes_per_sec_handles_zero_elapsed_time() {
+        assert_eq!(bytes_per_sec(1000, Duration::ZERO), 0.0);
+        assert_eq!(bytes_per_sec(1000, Duration::from_secs(2)), 500.0);
+        assert_eq!(bytes_per_sec(0, Duration::from_secs(2)), 0.0);
+    }
+
+    /// A meter whose samples were taken `ago` the given durations, oldest first.
+    fn meter(samples: &[(Duration, u64)]) -> RateMeter {
+        let now = Instant::now();
+        RateMeter { samples: samples.iter().map(|&(ago, done)| (now - ago, done)).collect() }
+    }
+
+    #[test]
+    fn rate_meter_reports_a_steady_rate_and_time_left() {
+        let (rate, eta) = meter(&[(Duration::from_secs(2), 0), (Duration::from_secs(1), 1000)]).record(2000, 10_000);
+        assert_eq!(rate, 1000);
+        // The clock moves on a little after the samples, so the rate is just under 1000.
+        assert!(matches!(eta, Some(8 | 9)), "{eta:?}");
+    }
+
+    #[test]
+    fn rate_meter_averages_over_the_recent_window() {
+        // The burst 20s ago is dropped; the newest sample before the window is the baseline.
+        let mut meter = meter(&[(Duration::from_secs(20), 0), (Duration::from_secs(10), 90_000), (Duration::from_secs(2), 98_000)]);
+        let (rate, _) = meter.record(100_000, 100_000);
+        assert_eq!(meter.samples.len(), 3);
+        assert_eq!(meter.samples[0].1, 90_000);
+        assert_eq!(rate, 1000);
+    }
+
+    #[test]
+    fn rate_meter_knows_no_time_left_before_data_moves() {
+        assert_eq!(RateMeter::new().record(0, 100), (0, None));
+    }
+
+    #[test]
+    fn zip_notes_dir_skips_and_reports_invalid_utf8_notes() {
+        let dir = library(&[("a", "A"), ("bad", "")]);
+        fs::write(note_file(&dir.0, "bad"), [b'o', b'k', 0xff, 0xfe]).unwrap();
+        let out = temp_dir();
+        let zip_path = out.0.join("backup.zip");
+        let archived = zip_notes_dir(&dir.0, &zip_path).unwrap();
+        assert_eq!(archived.notes, 1);
+        assert_eq!(archived.skipped, ["bad.md"]);
+        let mut entries = zip_entries(&zip_path);
+        entries.sort();
+        assert_eq!(entries, ["a.md", "index.json"]);
+        let extracted = out.0.join("extracted");
+        unzip_into(&extracted, &zip_path).unwrap();
+        let ids: Vec<String> = load_index_from(&extracted).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["a"]);
+    }
+
+    /// A heartbeat every `interval` and the number of beats so far.
+    fn counted_heartbeat(interval: Duration) -> (Heartbeat, Arc<AtomicUsize>) {
+        let beats = Arc::new(AtomicUsize::new(0));
+        let counter = beats.clone();
+        (Heartbeat::every(interval, move || { counter.fetch_add(1, Ordering::SeqCst); }), beats)
+    }
+
+    #[test]
+    fn heartbeat_fires_during_a_quiet_transfer() {
+        let (heartbeat, beats) = counted_heartbeat(Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(150));
+        drop(heartbeat);
+        let fired = beats.load(Ordering::SeqCst);
+        assert!(fired >= 1, "no heartbeat");
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(beats.load(Ordering::SeqCst), fired, "kept beating after being dropped");
+    }
+
+    #[test]
+    fn heartbeat_stays_quiet_while_progress_flows() {
+        let (heartbeat, beats) = counted_heartbeat(Duration::from_millis(200));
+        for _ in 0..30 {
+            std::thread::sleep(Duration::from_millis(10));
+            heartbeat.touch();
+        }
+        assert_eq!(beats.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn entry_name_escapes_separators_in_components() {
+        assert_eq!(entry_name(&[ATTACHMENTS_DIR, "n1", "a/b:c%.png"]), "attachments/n1/a%2Fb%3Ac%25.png");
+    }
+
+    #[test]
+    fn entry_path_reverses_entry_name() {
+        let name = entry_name(&[ATTACHMENTS_DIR, "n1", "x:y%z?.png"]);
+        assert_eq!(entry_path(&name), Some(PathBuf::from(ATTACHMENTS_DIR).join("n1").join("x:y%z?.png")));
+        assert_eq!(entry_path("index.json"), Some(PathBuf::from("index.json")));
+    }
+
+    #[test]
+    fn entry_path_rejects_names_outside_the_folder() {
+        for name in ["", "../x", "a/../b", "/etc/passwd", "a//b", "%2E%2E/x", "a%2Fb", "a%5Cb"] {
+            assert_eq!(entry_path(name), None, "{name}");
+        }
+    }
+
+    /// A notes folder with a note per `(id, updated_at, content)`, all in the index.
+    fn dated_folder(notes: &[(&str, &str, &str)]) -> ScratchGuard {
+        let dir = temp_dir();
+        for (id, _, content) in notes {
+            fs::write(note_file(&dir.0, id), content).unwrap();
+        }
+        let index: Vec<StoredNoteMetadata> = notes.iter().map(|(id, at, _)| meta(id, at)).collect();
+        fs::write(dir.0.join("index.json"), index_json(&index).unwrap()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merge_counts_every_kind_of_outcome() {
+        let (day1, day2) = ("2024-01-01T00:00:00Z", "2024-01-02T00:00:00Z");
+        let dest = dated_folder(&[("older", day2, "mine"), ("newer", day1, "mine"), ("tie", day1, "mine"), ("same", day1, "same")]);
+        let incoming_notes = [("older", day1, "theirs"), ("newer", day2, "theirs"), ("tie", day1, "theirs"), ("same", day1, "same"), ("d", day1, "d"), ("e", day1, "e")];
+        let incoming = dated_folder(&incoming_notes);
+        let incoming_index: Vec<StoredNoteMetadata> = incoming_notes.iter().map(|(id, at, _)| meta(id, at)).collect();
+
+        let outcome = merge_index(&dest.0, &incoming.0, &incoming_index, MergeStrategy::KeepBoth, Duration::ZERO, "peer").unwrap();
+        assert_eq!(outcome.stats(), serde_json::json!({ "received": 6, "added": 2, "updated": 1, "skipped": 2, "conflicted": 1 }));
+        assert_eq!(outcome.summary(), "Received 6 notes: 2 new, 1 updated, 1 kept as copies, 2 unchanged");
+        assert_eq!(outcome.added, ["d", "e"]);
+        assert_eq!(outcome.updated, ["newer"]);
+    }
+
+    #[test]
+    fn merge_summary_leaves_out_empty_parts() {
+        let outcome = |added: &[&str], received, skipped| MergeOutcome {
+            added: added.iter().map(|id| id.to_string()).collect(),
+            updated: Vec::new(),
+            kept_both: Vec::new(),
+            received,
+            skipped,
+        };
+        assert_eq!(outcome(&[], 0, 0).summary(), "Nothing to import");
+        assert_eq!(outcome(&["a"], 1, 0).summary(), "Received 1 note: 1 new");
+        assert_eq!(outcome(&[], 3, 3).summary(), "Received 3 notes: 3 unchanged");
+    }
+
+    #[test]
+    fn txt_notes_keep_their_extension_through_a_transfer() {
+        let source = temp_dir();
+        fs::write(source.0.join("a.txt"), "plain").unwrap();
+        let mut note = meta("a", "2024-01-01T00:00:00Z");
+        note.extension = Some("txt".into());
+        fs::write(source.0.join("index.json"), index_json(&[note]).unwrap()).unwrap();
+
+        let out = temp_dir();
+        let zip_path = out.0.join("notes.zip");
+        zip_notes_dir(&source.0, &zip_path).unwrap();
+        assert!(zip_entries(&zip_path).contains(&"a.txt".to_string()));
+
+        let dest = temp_dir();
+        let outcome = import_archive(&dest.0, &zip_path, &out.0.join("extracted"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert_eq!(outcome.added, ["a"]);
+        assert_eq!(fs::read_to_string(dest.0.join("a.txt")).unwrap(), "plain");
+        assert!(!dest.0.join("a.md").exists());
+        assert_eq!(load_index_from(&dest.0).unwrap()[0].extension.as_deref(), Some("txt"));
+    }
+
+    #[test]
+    fn two_listeners_can_share_the_discovery_port() {
+        let first = bind_discovery_listener(DISCOVERY_PORT).unwrap();
+        let second = bind_discovery_listener(DISCOVERY_PORT).unwrap();
+        assert_eq!(first.local_addr().unwrap().port(), DISCOVERY_PORT);
+        assert_eq!(second.local_addr().unwrap().port(), DISCOVERY_PORT);
+    }
+
+    #[test]
+    fn broadcasts_for_each_subnet_then_global() {
+        let at = |a, b, c, d| SocketAddr::from((Ipv4Addr::new(a, b, c, d), DISCOVERY_PORT));
+        let interfaces = [
+            (Ipv4Addr::new(192, 168, 1, 5), Ipv4Addr::new(255, 255, 255, 0)),
+            (Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(255, 0, 0, 0)),
+        ];
+        assert_eq!(broadcasts_for(&interfaces, None, DISCOVERY_PORT), [at(192, 168, 1, 255), at(10, 255, 255, 255), at(255, 255, 255, 255)]);
+        assert_eq!(broadcasts_for(&interfaces, Some(Ipv4Addr::new(10, 0, 0, 2)), DISCOVERY_PORT), [at(10, 255, 255, 255), at(255, 255, 255, 255)]);
+        assert_eq!(broadcasts_for(&[], None, DISCOVERY_PORT), [at(255, 255, 255, 255)]);
+    }
+
+    #[test]
+    fn outgoing_zip_is_removed_when_building_it_fails() {
+        let notes = temp_dir();
+        fs::write(notes.0.join("a.md"), "x").unwrap();
+        fs::write(notes.0.join("index.json"), "{ not json").unwrap();
+        let out = temp_dir();
+        let tmp_zip = out.0.join("outgoing_notes.zip");
+
+        let res = with_outgoing_zip(tmp_zip.clone(), |zip| zip_notes_dir(&notes.0, zip).map(|_| ()), |_| -> Result<(), AppError> { panic!("nothing to send") });
+        assert!(res.is_err());
+        assert!(!tmp_zip.exists());
+    }
+
+    #[test]
+    fn outgoing_zip_is_removed_after_sending() {
+        let notes = library(&[("a", "text")]);
+        let out = temp_dir();
+        let tmp_zip = out.0.join("outgoing_notes.zip");
+
+        let sent = with_outgoing_zip(tmp_zip.clone(), |zip| zip_notes_dir(&notes.0, zip).map(|_| ()), |zip| Ok(zip_entries(zip))).unwrap();
+        assert!(sent.contains(&"a.md".to_string()));
+        assert!(!tmp_zip.exists());
+    }
+
+    #[test]
+    fn an_undiscoverable_receiver_ignores_pings_but_still_accepts_transfers() {
+        let responder = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let ping = ping_bytes("Laptop", "dev-2").unwrap();
+        let pong = || pong_bytes("Desk", "dev-1", 4000, None).unwrap();
+
+        client.send_to(&ping, responder.local_addr().unwrap()).unwrap();
+        assert!(!answer_ping(&responder, || false, pong).unwrap());
+        let mut buf = [0u8; 2048];
+        assert!(client.recv_from(&mut buf).is_err());
+
+        let listener = bind_with_fallback(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let _client = std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        assert!(listener.accept().is_ok());
+
+        client.send_to(&ping, responder.local_addr().unwrap()).unwrap();
+        assert!(answer_ping(&responder, || true, pong).unwrap());
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        let reply: DiscoveryPing = serde_json::from_slice(&buf[..n]).unwrap();
+        assert_eq!((reply.kind.as_str(), reply.transfer_port), ("pong", 4000));
+    }
+
+    #[test]
+    fn first_pong_skips_stray_packets() {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let peer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let to = udp.local_addr().unwrap();
+        peer.send_to(b"garbage", to).unwrap();
+        peer.send_to(&ping_bytes("Laptop", "dev-2").unwrap(), to).unwrap();
+        peer.send_to(&pong_bytes("Desk", "dev-1", 4000, Some(3)).unwrap(), to).unwrap();
+
+        let (from, msg) = first_pong(&udp, Duration::from_secs(2)).unwrap();
+        assert_eq!(from, peer.local_addr().unwrap());
+        assert_eq!((msg.name.as_str(), msg.transfer_port), ("Desk", 4000));
+    }
+
+    #[test]
+    fn first_pong_gives_up_at_the_deadline() {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        assert!(matches!(first_pong(&udp, Duration::from_millis(100)), Err(AppError::Network(_))));
+    }
+
+    #[test]
+    fn stopping_a_scan_returns_the_peers_found_so_far_promptly() {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let peer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        peer.send_to(&pong_bytes("Desk", "dev-1", 4000, None).unwrap(), udp.local_addr().unwrap()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopper = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                stop.store(true, Ordering::SeqCst);
+            })
+        };
+
+        let start = Instant::now();
+        let peers = collect_pongs(&udp, Duration::from_secs(30), || stop.load(Ordering::SeqCst));
+        stopper.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(peers.len(), 1);
+        assert_eq!((peers[0].name.as_str(), peers[0].port), ("Desk", 4000));
+    }
+
+    #[test]
+    fn index_from_files_describes_each_readable_note_file() {
+        let dir = temp_dir();
+        fs::write(dir.0.join("a.md"), "# Alpha\nbody").unwrap();
+        fs::write(dir.0.join("b.txt"), "plain words").unwrap();
+        fs::write(dir.0.join("c.md"), [0xff, 0xfe]).unwrap();
+        fs::write(dir.0.join("notes.json"), "{}").unwrap();
+        fs::create_dir_all(dir.0.join(ATTACHMENTS_DIR).join("a")).unwrap();
+
+        let mut index = index_from_files(&dir.0).unwrap();
+        index.sort_by(|x, y| x.id.cmp(&y.id));
+        let described: Vec<(&str, &str, Option<&str>)> = index.iter().map(|m| (m.id.as_str(), m.title.as_str(), m.extension.as_deref())).collect();
+        assert_eq!(described, [("a", "Alpha", None), ("b", "plain words", Some("txt"))]);
+        assert!(index.iter().all(|m| parse_timestamp(&m.updated_at).is_some()));
+    }
+
+    /// A zip holding `files` as given, for archives a well-behaved sender wouldn't produce.
+    fn raw_archive(dir: &Path, files: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join("raw.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn an_archive_without_an_index_is_rebuilt_from_its_files() {
+        let out = temp_dir();
+        let zip_path = raw_archive(&out.0, &[("a.md", "# From files\nbody")]);
+        let dest = temp_dir();
+
+        let outcome = import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert_eq!(outcome.added, ["a"]);
+        assert_eq!(load_index_from(&dest.0).unwrap()[0].title, "From files");
+        assert_eq!(fs::read_to_string(dest.0.join("a.md")).unwrap(), "# From files\nbody");
+    }
+
+    #[test]
+    fn an_archive_with_an_empty_index_merges_nothing() {
+        let out = temp_dir();
+        let zip_path = raw_archive(&out.0, &[("index.json", "[]"), ("a.md", "stray")]);
+        let dest = library(&[("b", "mine")]);
+
+        let outcome = import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert!(outcome.added.is_empty() && outcome.updated.is_empty());
+        assert!(!dest.0.join("a.md").exists());
+        assert_eq!(load_index_from(&dest.0).unwrap().len(), 1);
+    }
+
+    fn described(mut note: StoredNoteMetadata) -> StoredNoteMetadata {
+        note.tags = vec!["work".into(), "ideas".into()];
+        note.color = Some("#ffcc00".into());
+        note.created_at = Some("2023-06-01T00:00:00Z".into());
+        note
+    }
+
+    fn full_metadata(note: &StoredNoteMetadata) -> (Option<&str>, Option<&str>, &[String]) {
+        (note.created_at.as_deref(), note.color.as_deref(), &note.tags)
+    }
+
+    #[test]
+    fn transfers_keep_created_at_tags_and_color() {
+        let src = notes_folder(described(meta("a", "2024-01-02T00:00:00Z")), "body");
+        let expected = described(meta("a", "2024-01-02T00:00:00Z"));
+        let out = temp_dir();
+        let all_zip = out.0.join("all.zip");
+        let single_zip = out.0.join("single.zip");
+        zip_notes_dir(&src.0, &all_zip).unwrap();
+        zip_single_note(&src.0, "a", &single_zip).unwrap();
+
+        for zip_path in [&all_zip, &single_zip] {
+            let dest = temp_dir();
+            import_archive(&dest.0, zip_path, &out.0.join("extract"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+            let received = load_index_from(&dest.0).unwrap();
+            assert_eq!(full_metadata(&received[0]), full_metadata(&expected));
+        }
+    }
+
+    #[test]
+    fn a_winning_local_entry_keeps_its_fields_and_the_earlier_creation_time() {
+        let mut local = meta("a", "2024-01-03T00:00:00Z");
+        local.tags = vec!["mine".into()];
+        local.created_at = Some("2024-01-01T00:00:00Z".into());
+        let dest = notes_folder(local, "local");
+        let src = notes_folder(described(meta("a", "2024-01-02T00:00:00Z")), "theirs");
+        let out = temp_dir();
+        let zip_path = out.0.join("all.zip");
+        zip_notes_dir(&src.0, &zip_path).unwrap();
+
+        let outcome = import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert!(outcome.updated.is_empty());
+        let kept = &load_index_from(&dest.0).unwrap()[0];
+        assert_eq!(full_metadata(kept), (Some("2023-06-01T00:00:00Z"), None, &["mine".to_string()][..]));
+        assert_eq!(fs::read_to_string(dest.0.join("a.md")).unwrap(), "local");
+    }
+
+    #[test]
+    fn has_room_needs_twice_the_size() {
+        assert!(has_room(None, u64::MAX));
+        assert!(has_room(Some(10), 5));
+        assert!(!has_room(Some(9), 5));
+        assert!(!has_room(Some(u64::MAX - 1), u64::MAX));
+    }
+
+    #[test]
+    fn offers_that_would_fill_the_disk_are_rejected() {
+        let peer = SocketAddr::from((Ipv4Addr::LOCALHOST, TRANSFER_PORT));
+        let header = offer(None, false);
+        let settings = Settings::default();
+        assert!(matches!(screen_offer(&header, peer, &settings, 1024, Some(19), false), OfferDecision::Reject(message) if message.contains("disk space")));
+        assert_eq!(screen_offer(&header, peer, &settings, 1024, Some(20), false), OfferDecision::Offer { trusted: false });
+        assert_eq!(screen_offer(&header, peer, &settings, 1024, None, false), OfferDecision::Offer { trusted: false });
+    }
+
+    /// Merges an incoming copy of note `a` with `content` under a five second skew tolerance.
+    fn skewed_merge(local_at: &str, incoming_at: &str, content: &str) -> (ScratchGuard, MergeOutcome) {
+        let dest = notes_folder(meta("a", local_at), "local");
+        let incoming = notes_folder(meta("a", incoming_at), content);
+        let outcome = merge_index(&dest.0, &incoming.0, &[meta("a", incoming_at)], MergeStrategy::Newest, Duration::from_secs(5), "peer").unwrap();
+        (dest, outcome)
+    }
+
+    #[test]
+    fn identical_notes_within_the_skew_are_left_alone() {
+        let (dest, outcome) = skewed_merge("2024-01-01T00:00:00Z", "2024-01-01T00:00:03Z", "local");
+        assert!(outcome.updated.is_empty() && outcome.kept_both.is_empty());
+        assert_eq!(outcome.skipped, 1);
+        assert_eq!(load_index_from(&dest.0).unwrap()[0].updated_at, "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn different_notes_within_the_skew_are_kept_as_a_copy() {
+        let (dest, outcome) = skewed_merge("2024-01-01T00:00:00Z", "2024-01-01T00:00:03Z", "theirs");
+        assert!(outcome.updated.is_empty());
+        assert_eq!(outcome.kept_both.len(), 1);
+        assert_eq!(fs::read_to_string(dest.0.join("a.md")).unwrap(), "local");
+        assert_eq!(fs::read_to_string(note_file(&dest.0, &outcome.kept_both[0])).unwrap(), "theirs");
+    }
+
+    #[test]
+    fn a_note_newer_by_more_than_the_skew_wins() {
+        let (dest, outcome) = skewed_merge("2024-01-01T00:00:00Z", "2024-01-01T00:00:10Z", "theirs");
+        assert_eq!(outcome.updated, ["a"]);
+        assert!(outcome.kept_both.is_empty());
+        assert_eq!(load_index_from(&dest.0).unwrap()[0].updated_at, "2024-01-01T00:00:10Z");
+    }
+
+    /// The single backup folder `replace_index` made in `dir`.
+    fn only_backup(dir: &Path) -> PathBuf {
+        let backups: Vec<PathBuf> = fs::read_dir(dir.join(BACKUPS_DIR)).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(backups.len(), 1);
+        backups[0].clone()
+    }
+
+    #[test]
+    fn replacing_drops_local_only_notes_and_backs_up_the_old_library() {
+        let dest = library(&[("a", "local only"), ("b", "old b")]);
+        fs::create_dir_all(attachment_dir(&dest.0, "a")).unwrap();
+        fs::write(attachment_dir(&dest.0, "a").join("pic.png"), "png").unwrap();
+        let src = library(&[("b", "new b"), ("c", "new c")]);
+        let out = temp_dir();
+        let zip_path = out.0.join("all.zip");
+        zip_notes_dir(&src.0, &zip_path).unwrap();
+
+        let outcome = import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Replace, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        let mut added = outcome.added.clone();
+        added.sort();
+        assert_eq!(added, ["b", "c"]);
+        let mut ids: Vec<String> = load_index_from(&dest.0).unwrap().into_iter().map(|m| m.id).collect();
+        ids.sort();
+        assert_eq!(ids, ["b", "c"]);
+        assert!(!dest.0.join("a.md").exists());
+        assert!(!attachment_dir(&dest.0, "a").exists());
+        assert_eq!(fs::read_to_string(dest.0.join("b.md")).unwrap(), "new b");
+
+        let backup = only_backup(&dest.0);
+        assert_eq!(fs::read_to_string(backup.join("a.md")).unwrap(), "local only");
+        assert_eq!(fs::read_to_string(backup.join("b.md")).unwrap(), "old b");
+        assert_eq!(load_index_from(&backup).unwrap().len(), 2);
+        assert!(backup.join(ATTACHMENTS_DIR).join("a").join("pic.png").exists());
+    }
+
+    #[test]
+    fn replacing_with_an_archive_without_notes_changes_nothing() {
+        let dest = library(&[("a", "mine")]);
+        let out = temp_dir();
+        let zip_path = raw_archive(&out.0, &[("index.json", "[]")]);
+
+        let res = import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Replace, MergeStrategy::Newest, Duration::ZERO, "peer");
+        assert!(matches!(res, Err(AppError::InvalidInput(_))));
+        assert_eq!(fs::read_to_string(dest.0.join("a.md")).unwrap(), "mine");
+        assert!(!dest.0.join(BACKUPS_DIR).exists());
+    }
+
+    #[test]
+    fn merging_keeps_local_only_notes() {
+        let dest = library(&[("a", "local only")]);
+        let src = library(&[("c", "new c")]);
+        let out = temp_dir();
+        let zip_path = out.0.join("all.zip");
+        zip_notes_dir(&src.0, &zip_path).unwrap();
+
+        import_archive(&dest.0, &zip_path, &out.0.join("extract"), ImportMode::Merge, MergeStrategy::Newest, Duration::ZERO, "peer").unwrap();
+        assert_eq!(load_index_from(&dest.0).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(dest.0.join("a.md")).unwrap(), "local only");
+        assert!(!dest.0.join(BACKUPS_DIR).exists());
+    }
 }
@@ -0,0 +1,120 @@
+use std::{fs, path::Path};
+
+use serde::Serialize;
+use tauri::AppHandle;
+use crate::{error::AppError, load_index_in, markdown::plain_text, indexed_note_path, note_file, notes_dir, parse_timestamp};
+
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteStats {
+    words: usize,
+    /// Characters of the rendered text, i.e. without markdown syntax or line breaks.
+    characters: usize,
+    lines: usize,
+    reading_minutes: usize,
+}
+
+fn stats_for(content: &str) -> NoteStats {
+    let text = plain_text(content);
+    let words = text.split_whitespace().count();
+    NoteStats {
+        words,
+        characters: text.chars().filter(|c| *c != '\n').count(),
+        lines: content.lines().count(),
+        reading_minutes: words.div_ceil(WORDS_PER_MINUTE),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentNote {
+    id: String,
+    title: String,
+    created_at: String,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStats {
+    notes: usize,
+    /// Size of the note files on disk.
+    bytes: u64,
+    words: usize,
+    oldest_updated_at: Option<String>,
+    newest_updated_at: Option<String>,
+    most_recently_created: Option<RecentNote>,
+}
+
+#[tauri::command]
+pub fn notes_summary_stats(app: AppHandle) -> Result<LibraryStats, AppError> {
+    library_stats(&notes_dir(&app)?)
+}
+
+fn library_stats(dir: &Path) -> Result<LibraryStats, AppError> {
+    let index = load_index_in(dir)?;
+    let mut stats = LibraryStats { notes: index.len(), ..LibraryStats::default() };
+
+    for meta in &index {
+        let path = note_file(dir, &meta.id);
+        stats.bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if let Ok(content) = fs::read_to_string(&path) {
+            stats.words += stats_for(&content).words;
+        }
+    }
+
+    stats.oldest_updated_at = index.iter().min_by_key(|m| parse_timestamp(&m.updated_at)).map(|m| m.updated_at.clone());
+    stats.newest_updated_at = index.iter().max_by_key(|m| parse_timestamp(&m.updated_at)).map(|m| m.updated_at.clone());
+    stats.most_recently_created = index
+        .iter()
+        .filter_map(|m| m.created_at.as_ref().map(|created| (m, created)))
+        .max_by_key(|(_, created)| parse_timestamp(created))
+        .map(|(m, created)| RecentNote { id: m.id.clone(), title: m.title.clone(), created_at: created.clone() });
+
+    Ok(stats)
+}
+
+#[tauri::command]
+pub fn note_stats(app: AppHandle, id: String) -> Result<NoteStats, AppError> {
+    let content = fs::read_to_string(indexed_note_path(&app, &id)?)?;
+    Ok(stats_for(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_rendered_words_and_characters() {
+        let stats = stats_for("# Title\n\nSome **bold** [link](https://example.com) text.\n");
+        assert_eq!(stats.words, 5);
+        assert_eq!(stats.characters, "TitleSome bold link text.".len());
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.reading_minutes, 1);
+    }
+
+    #[test]
+    fn reading_time_rounds_up_to_whole_minutes() {
+        assert_eq!(stats_for("").reading_minutes, 0);
+        assert_eq!(stats_for(&"word ".repeat(200)).reading_minutes, 1);
+        assert_eq!(stats_for(&"word ".repeat(201)).reading_minutes, 2);
+    }
+
+    #[test]
+    fn library_stats_total_every_note() {
+        let dir = crate::testing::notes_folder(&[("a", "A", "one two"), ("b", "B", "three")]);
+        let mut index = crate::load_index_in(dir.path()).unwrap();
+        index[0].updated_at = "2024-03-01T00:00:00Z".into();
+        index[1].created_at = Some("2024-02-01T00:00:00Z".into());
+        // A listed note whose file is gone still counts as a note, with no words.
+        index.push(crate::testing::meta("gone", "Gone"));
+        fs::write(dir.path().join("index.json"), crate::index_json(&index).unwrap()).unwrap();
+
+        let stats = library_stats(dir.path()).unwrap();
+        assert_eq!((stats.notes, stats.words, stats.bytes), (3, 3, 12));
+        assert_eq!(stats.oldest_updated_at.as_deref(), Some("2024-01-01T00:00:00Z"));
+        assert_eq!(stats.newest_updated_at.as_deref(), Some("2024-03-01T00:00:00Z"));
+        assert_eq!(stats.most_recently_created.map(|note| note.id).as_deref(), Some("b"));
+    }
+}
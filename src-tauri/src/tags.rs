@@ -0,0 +1,130 @@
+use std::{collections::HashMap, fs, sync::Mutex, time::SystemTime};
+
+use once_cell::sync::Lazy;
+use tauri::AppHandle;
+
+use crate::{error::AppError, index_path, load_index, update_metadata, NoteSummary, StoredNoteMetadata};
+
+const DEFAULT_SUGGESTIONS: usize = 10;
+
+/// `index.json`'s mtime and every tag with its note count, most used first.
+type CachedCounts = (SystemTime, Vec<(String, usize)>);
+
+/// Cleared whenever the app saves the index; the mtime catches writes made elsewhere, like merges.
+static TAG_COUNTS: Lazy<Mutex<Option<CachedCounts>>> = Lazy::new(|| Mutex::new(None));
+
+pub(crate) fn invalidate_tag_counts() {
+    *TAG_COUNTS.lock().unwrap() = None;
+}
+
+/// Tags are stored trimmed, lowercased and without a leading `#`, so `#Work` and `work` are one tag.
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().trim_start_matches('#').trim().to_lowercase();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.contains(',') {
+            return Err(AppError::InvalidInput(format!("Tags can't contain commas: {tag}")));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+fn tag_counts(app: &AppHandle) -> Result<Vec<(String, usize)>, AppError> {
+    let modified = fs::metadata(index_path(app)?).and_then(|m| m.modified()).ok();
+    if let (Some(modified), Some((cached_at, counts))) = (modified, TAG_COUNTS.lock().unwrap().as_ref()) {
+        if *cached_at == modified {
+            return Ok(counts.clone());
+        }
+    }
+
+    let counts = count_tags(load_index(app)?);
+    if let Some(modified) = modified {
+        *TAG_COUNTS.lock().unwrap() = Some((modified, counts.clone()));
+    }
+    Ok(counts)
+}
+
+/// Every tag in `index` with the number of notes using it, most used first
+/// and alphabetical among equals.
+fn count_tags(index: Vec<StoredNoteMetadata>) -> Vec<(String, usize)> {
+    let mut by_tag: HashMap<String, usize> = HashMap::new();
+    for meta in index {
+        for tag in meta.tags {
+            *by_tag.entry(tag).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(String, usize)> = by_tag.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Whether every character of `needle` appears in `tag` in order, e.g. `prj` in `project`.
+fn is_subsequence(needle: &str, tag: &str) -> bool {
+    let mut chars = tag.chars();
+    needle.chars().all(|c| chars.any(|t| t == c))
+}
+
+/// Replaces a note's tags without touching its `updated_at`.
+#[tauri::command]
+pub fn set_note_tags(app: AppHandle, id: String, tags: Vec<String>) -> Result<NoteSummary, AppError> {
+    let tags = normalize_tags(tags)?;
+    update_metadata(&app, &id, |meta| meta.tags = tags)
+}
+
+/// Existing tags for an autocomplete, most used first. Tags starting with `prefix`
+/// come before ones that only contain its letters in order; an empty prefix
+/// returns the most used tags.
+#[tauri::command]
+pub fn suggest_tags(app: AppHandle, prefix: String, limit: Option<usize>) -> Result<Vec<String>, AppError> {
+    Ok(suggestions(tag_counts(&app)?, &prefix, limit.unwrap_or(DEFAULT_SUGGESTIONS)))
+}
+
+fn suggestions(counts: Vec<(String, usize)>, prefix: &str, limit: usize) -> Vec<String> {
+    let prefix = prefix.trim().trim_start_matches('#').to_lowercase();
+    let (starts, rest): (Vec<_>, Vec<_>) = counts.into_iter().partition(|(tag, _)| tag.starts_with(&prefix));
+    let fuzzy = rest.into_iter().filter(|(tag, _)| is_subsequence(&prefix, tag));
+    starts.into_iter().chain(fuzzy).map(|(tag, _)| tag).take(limit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(tags: &[&[&str]]) -> Vec<StoredNoteMetadata> {
+        tags.iter()
+            .enumerate()
+            .map(|(i, tags)| {
+                let mut meta = crate::testing::meta(&i.to_string(), "");
+                meta.tags = tags.iter().map(|tag| tag.to_string()).collect();
+                meta
+            })
+            .collect()
+    }
+
+    #[test]
+    fn count_tags_orders_by_use() {
+        let counts = count_tags(tagged(&[&["work", "home"], &["work"], &["art", "home"], &["work"]]));
+        assert_eq!(counts, [("work".to_string(), 3), ("home".to_string(), 2), ("art".to_string(), 1)]);
+    }
+
+    #[test]
+    fn suggestions_put_prefix_matches_before_fuzzy_ones() {
+        let counts = count_tags(tagged(&[&["project"], &["pr-review", "project"], &["print"], &["paper", "project"]]));
+        assert_eq!(suggestions(counts.clone(), "#PR", 10), ["project", "pr-review", "print", "paper"]);
+        assert_eq!(suggestions(counts.clone(), "prj", 10), ["project"]);
+        assert_eq!(suggestions(counts.clone(), "pp", 10), ["paper"]);
+        assert_eq!(suggestions(counts, "", 2), ["project", "paper"]);
+    }
+
+    #[test]
+    fn normalize_tags_trims_lowercases_and_dedups() {
+        assert_eq!(normalize_tags(vec![" #Work ".into(), "work".into(), "".into(), "Home".into()]).unwrap(), ["work", "home"]);
+        assert!(matches!(normalize_tags(vec!["a,b".into()]), Err(AppError::InvalidInput(_))));
+    }
+}
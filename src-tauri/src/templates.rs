@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tauri::AppHandle;
+use time::macros::format_description;
+
+use crate::{build_summary, create_note, error::AppError, load_index, notes_dir, settings::load_settings, NoteSummary};
+
+/// Lives inside the notes folder but outside the index, so templates never show up as notes.
+pub(crate) const TEMPLATES_DIR: &str = "templates";
+
+#[derive(Serialize)]
+pub struct Template {
+    name: String,
+    content: String,
+}
+
+fn templates_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let dir = notes_dir(app)?.join(TEMPLATES_DIR);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Template names become file names, so anything that could escape the folder is refused.
+fn template_path(app: &AppHandle, name: &str) -> Result<PathBuf, AppError> {
+    template_file(&templates_dir(app)?, name)
+}
+
+fn template_file(dir: &Path, name: &str) -> Result<PathBuf, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(AppError::InvalidInput(format!("Invalid template name: {name}")));
+    }
+    Ok(dir.join(format!("{name}.md")))
+}
+
+/// The current time in the user's timezone, so "today" matches their calendar.
+/// Falls back to UTC when the local offset can't be determined.
+fn local_now() -> time::OffsetDateTime {
+    time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+}
+
+/// Fills in `{{date}}`, `{{time}}` and `{{title}}`.
+fn apply_placeholders(template: &str, title: &str) -> String {
+    let now = local_now();
+    let date = now.format(format_description!("[year]-[month]-[day]")).unwrap_or_default();
+    let clock = now.format(format_description!("[hour]:[minute]")).unwrap_or_default();
+    template
+        .replace("{{date}}", &date)
+        .replace("{{time}}", &clock)
+        .replace("{{title}}", title)
+}
+
+#[tauri::command]
+pub fn list_templates(app: AppHandle) -> Result<Vec<Template>, AppError> {
+    templates_in(&templates_dir(&app)?)
+}
+
+fn templates_in(dir: &Path) -> Result<Vec<Template>, AppError> {
+    let mut templates = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("md") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        templates.push(Template { name, content: fs::read_to_string(&path)? });
+    }
+    templates.sort_by_key(|t| t.name.to_lowercase());
+    Ok(templates)
+}
+
+/// Creates or overwrites a template.
+#[tauri::command]
+pub fn save_template(app: AppHandle, name: String, content: String) -> Result<(), AppError> {
+    fs::write(template_path(&app, &name)?, content)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn create_note_from_template(app: AppHandle, template_name: String, title: String) -> Result<NoteSummary, AppError> {
+    let template = fs::read_to_string(template_path(&app, &template_name)?)
+        .map_err(|_| AppError::NotFound(format!("Template {template_name} not found")))?;
+    let content = apply_placeholders(&template, &title);
+    create_note(app, title, content, None)
+}
+
+/// Opens today's daily note, creating it from the configured template on first use.
+#[tauri::command]
+pub fn daily_note(app: AppHandle) -> Result<NoteSummary, AppError> {
+    let title = daily_title(local_now().date());
+    if let Some(meta) = load_index(&app)?.into_iter().find(|meta| meta.title == title) {
+        return Ok(build_summary(&app, meta));
+    }
+
+    let template = load_settings(&app)
+        .daily_note_template
+        .and_then(|name| template_path(&app, &name).ok())
+        .and_then(|path| fs::read_to_string(path).ok());
+    let content = daily_content(template.as_deref(), &title);
+    create_note(app, title, content, None)
+}
+
+fn daily_title(date: time::Date) -> String {
+    format!("Daily {}", date.format(format_description!("[year]-[month]-[day]")).unwrap_or_default())
+}
+
+/// The configured template filled in, or a bare heading without one.
+fn daily_content(template: Option<&str>, title: &str) -> String {
+    match template {
+        Some(template) => apply_placeholders(template, title),
+        None => format!("# {title}\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let filled = apply_placeholders("# {{title}}\n{{date}} {{time}} {{title}}", "Standup");
+        let today = local_now().format(format_description!("[year]-[month]-[day]")).unwrap();
+        assert!(filled.starts_with(&format!("# Standup\n{today} ")), "{filled}");
+        assert!(filled.ends_with(" Standup") && !filled.contains("{{"), "{filled}");
+    }
+
+    #[test]
+    fn template_names_must_stay_in_the_folder() {
+        let dir = Path::new("/notes/templates");
+        assert_eq!(template_file(dir, " meeting ").unwrap(), dir.join("meeting.md"));
+        for name in ["", "  ", ".hidden", "../escape", "a/b", "a\\b"] {
+            assert!(matches!(template_file(dir, name), Err(AppError::InvalidInput(_))), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn templates_are_listed_by_name_and_other_files_skipped() {
+        let dir = TempDir::new();
+        fs::write(dir.path().join("weekly.md"), "w").unwrap();
+        fs::write(dir.path().join("Daily.md"), "d").unwrap();
+        fs::write(dir.path().join("notes.txt"), "x").unwrap();
+        let names: Vec<String> = templates_in(dir.path()).unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["Daily", "weekly"]);
+    }
+
+    #[test]
+    fn daily_notes_are_titled_by_date() {
+        let date = time::Date::from_calendar_date(2024, time::Month::March, 5).unwrap();
+        assert_eq!(daily_title(date), "Daily 2024-03-05");
+    }
+
+    #[test]
+    fn daily_content_uses_the_template_when_set() {
+        assert_eq!(daily_content(None, "Daily 2024-03-05"), "# Daily 2024-03-05\n");
+        assert_eq!(daily_content(Some("## {{title}}\n- [ ] "), "Daily 2024-03-05"), "## Daily 2024-03-05\n- [ ] ");
+    }
+}
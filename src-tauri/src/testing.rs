@@ -0,0 +1,46 @@
+//! Helpers shared by the unit tests.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{index_json, StoredNoteMetadata};
+
+/// A fresh folder under the system temp dir, removed again when dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("notetake-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub(crate) fn meta(id: &str, title: &str) -> StoredNoteMetadata {
+    serde_json::from_value(serde_json::json!({ "id": id, "title": title, "updatedAt": "2024-01-01T00:00:00Z" })).unwrap()
+}
+
+/// A notes folder with a `.md` file per `(id, title, content)` and an index listing them.
+pub(crate) fn notes_folder(notes: &[(&str, &str, &str)]) -> TempDir {
+    let dir = TempDir::new();
+    for (id, _, content) in notes {
+        fs::write(dir.path().join(format!("{id}.md")), content).unwrap();
+    }
+    let index: Vec<StoredNoteMetadata> = notes.iter().map(|(id, title, _)| meta(id, title)).collect();
+    fs::write(dir.path().join("index.json"), index_json(&index).unwrap()).unwrap();
+    dir
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, fs};
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{error::AppError, indexed_note_path, markdown::parser_options};
+
+#[derive(Serialize)]
+pub struct TocEntry {
+    text: String,
+    level: u8,
+    /// Slug of the heading text, unique within the note.
+    anchor: String,
+    /// Headings of a deeper level that follow this one.
+    children: Vec<TocEntry>,
+}
+
+/// GitHub-style slug: lowercase, spaces become `-`, other punctuation is dropped.
+fn slugify(text: &str) -> String {
+    let slug: String = text
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect();
+    if slug.is_empty() {
+        "heading".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Every heading in document order. Going through the markdown parser means
+/// `#` lines inside code blocks are never mistaken for headings.
+fn headings(content: &str) -> Vec<(u8, String)> {
+    let mut headings = Vec::new();
+    let mut current: Option<(u8, String)> = None;
+    for event in Parser::new_ext(content, parser_options()) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => current = Some((level as u8, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = current.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// A heading nests under the last sibling with a smaller level, however deep.
+fn insert(siblings: &mut Vec<TocEntry>, entry: TocEntry) {
+    match siblings.last_mut() {
+        Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+        _ => siblings.push(entry),
+    }
+}
+
+fn build_toc(content: &str) -> Vec<TocEntry> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut toc = Vec::new();
+    for (level, text) in headings(content) {
+        let slug = slugify(&text);
+        let count = seen.entry(slug.clone()).or_default();
+        let anchor = if *count == 0 { slug } else { format!("{slug}-{count}") };
+        *count += 1;
+        insert(&mut toc, TocEntry { text: text.trim().to_string(), level, anchor, children: Vec::new() });
+    }
+    toc
+}
+
+/// The note's outline. Repeated headings get `-1`, `-2`, ... anchors.
+#[tauri::command]
+pub fn note_toc(app: AppHandle, id: String) -> Result<Vec<TocEntry>, AppError> {
+    let content = fs::read_to_string(indexed_note_path(&app, &id)?)?;
+    Ok(build_toc(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(toc: &[TocEntry]) -> Vec<(u8, &str, usize)> {
+        toc.iter().map(|entry| (entry.level, entry.anchor.as_str(), entry.children.len())).collect()
+    }
+
+    #[test]
+    fn build_toc_nests_deeper_headings() {
+        let toc = build_toc("# Intro\n## Setup\n### Linux\n## Usage\n# Notes\n");
+        assert_eq!(outline(&toc), [(1, "intro", 2), (1, "notes", 0)]);
+        assert_eq!(outline(&toc[0].children), [(2, "setup", 1), (2, "usage", 0)]);
+        assert_eq!(toc[0].children[0].children[0].text, "Linux");
+    }
+
+    #[test]
+    fn build_toc_numbers_repeated_anchors() {
+        let toc = build_toc("## Step\n## Step\n## Step!\n## ???\n");
+        assert_eq!(outline(&toc), [(2, "step", 0), (2, "step-1", 0), (2, "step-2", 0), (2, "heading", 0)]);
+    }
+
+    #[test]
+    fn build_toc_skips_hashes_in_code_blocks() {
+        let toc = build_toc("```sh\n# not a heading\n```\n## Real `code`\n");
+        assert_eq!(outline(&toc), [(2, "real-code", 0)]);
+        assert_eq!(toc[0].text, "Real code");
+    }
+}
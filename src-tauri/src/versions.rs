@@ -0,0 +1,191 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tauri::AppHandle;
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, PrimitiveDateTime};
+
+use crate::{
+    build_summary, emit_note_changed, error::AppError, is_safe_id, load_index, load_index_in, lock_index, note_file,
+    note_path, notes_dir, now_timestamp, save_index, settings::load_settings, watcher, NoteSummary,
+};
+
+/// Earlier versions live in `notes/history/<note id>/<timestamp>.md`.
+pub(crate) const HISTORY_DIR: &str = "history";
+
+/// Version file names: sortable, millisecond precision, and free of `:` so they work on every OS.
+const VERSION_FORMAT: &[time::format_description::FormatItem<'static>] =
+    format_description!("[year][month][day]T[hour][minute][second][subsecond digits:3]Z");
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteVersion {
+    /// Identifies the version for `restore_note_version`.
+    timestamp: String,
+    /// When the version was replaced, as RFC3339.
+    saved_at: String,
+    size: u64,
+}
+
+fn history_dir(notes_dir: &Path, note_id: &str) -> PathBuf {
+    notes_dir.join(HISTORY_DIR).join(note_id)
+}
+
+/// The history folder of a note the index lists. Like a version's timestamp in
+/// `restore_note_version`, the id must be a known one, so it can't name another folder.
+fn indexed_history_dir(notes_dir: &Path, note_id: &str) -> Result<PathBuf, AppError> {
+    if !is_safe_id(note_id) || !load_index_in(notes_dir)?.iter().any(|meta| meta.id == note_id) {
+        return Err(AppError::NotFound(format!("Note {note_id} not found")));
+    }
+    Ok(history_dir(notes_dir, note_id))
+}
+
+/// Removes every saved version of a deleted note; unsafe ids remove nothing.
+pub(crate) fn remove_versions(notes_dir: &Path, note_id: &str) {
+    if !is_safe_id(note_id) {
+        return;
+    }
+    let _ = fs::remove_dir_all(history_dir(notes_dir, note_id));
+}
+
+/// Saved version stems, oldest first.
+fn version_stems(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut stems: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("md"))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .filter(|stem| PrimitiveDateTime::parse(stem, VERSION_FORMAT).is_ok())
+        .collect();
+    stems.sort();
+    stems
+}
+
+/// Copies the note's current file into its history before it is overwritten,
+/// then prunes the oldest versions beyond the configured limit. A limit of 0
+/// turns history off. Does nothing for a note without a file yet.
+pub(crate) fn snapshot(app: &AppHandle, note_id: &str) -> Result<(), AppError> {
+    snapshot_in(&notes_dir(app)?, note_id, load_settings(app).note_history_limit)
+}
+
+/// `snapshot` for a known notes folder, keeping at most `limit` versions.
+pub(crate) fn snapshot_in(notes_dir: &Path, note_id: &str, limit: usize) -> Result<(), AppError> {
+    let current = note_file(notes_dir, note_id);
+    if limit == 0 || !current.exists() {
+        return Ok(());
+    }
+    let dir = history_dir(notes_dir, note_id);
+    fs::create_dir_all(&dir)?;
+    let stamp = OffsetDateTime::now_utc().format(VERSION_FORMAT).unwrap_or_default();
+    fs::copy(&current, dir.join(format!("{stamp}.md")))?;
+
+    let stems = version_stems(&dir);
+    let excess = stems.len().saturating_sub(limit);
+    for stem in &stems[..excess] {
+        let _ = fs::remove_file(dir.join(format!("{stem}.md")));
+    }
+    Ok(())
+}
+
+/// Saved versions of a note, newest first.
+#[tauri::command]
+pub fn list_note_versions(app: AppHandle, id: String) -> Result<Vec<NoteVersion>, AppError> {
+    list_versions_in(&notes_dir(&app)?, &id)
+}
+
+fn list_versions_in(notes_dir: &Path, id: &str) -> Result<Vec<NoteVersion>, AppError> {
+    let dir = indexed_history_dir(notes_dir, id)?;
+    Ok(version_stems(&dir)
+        .into_iter()
+        .rev()
+        .map(|stem| {
+            let saved_at = PrimitiveDateTime::parse(&stem, VERSION_FORMAT)
+                .ok()
+                .and_then(|at| at.assume_utc().format(&Rfc3339).ok())
+                .unwrap_or_default();
+            let size = fs::metadata(dir.join(format!("{stem}.md"))).map(|m| m.len()).unwrap_or(0);
+            NoteVersion { timestamp: stem, saved_at, size }
+        })
+        .collect())
+}
+
+/// Puts an earlier version back as the note's content. The content being
+/// replaced is saved as a version first, so a restore can itself be undone.
+#[tauri::command]
+pub fn restore_note_version(app: AppHandle, id: String, timestamp: String) -> Result<NoteSummary, AppError> {
+    let _guard = lock_index(&app)?;
+    let dir = indexed_history_dir(&notes_dir(&app)?, &id)?;
+    // Only names from the listing are accepted, so `timestamp` can't point elsewhere.
+    if !version_stems(&dir).contains(&timestamp) {
+        return Err(AppError::NotFound(format!("Version {timestamp} not found")));
+    }
+    let content = fs::read(dir.join(format!("{timestamp}.md")))?;
+
+    let mut index = load_index(&app)?;
+    let meta = index
+        .iter_mut()
+        .find(|meta| meta.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("Note {id} not found")))?;
+    snapshot(&app, &id)?;
+    watcher::mark_own_write(&id);
+    fs::write(note_path(&app, &id)?, content)?;
+    meta.updated_at = now_timestamp();
+    let meta = meta.clone();
+    save_index(&app, &index)?;
+
+    let summary = build_summary(&app, meta);
+    emit_note_changed(&app, &summary);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::notes_folder;
+
+    #[test]
+    fn versions_are_listed_newest_first() {
+        let dir = notes_folder(&[("a", "A", "v1")]);
+        let history = history_dir(dir.path(), "a");
+        fs::create_dir_all(&history).unwrap();
+        fs::write(history.join("20240101T000000000Z.md"), "old").unwrap();
+        fs::write(history.join("20240102T000000000Z.md"), "newer").unwrap();
+        fs::write(history.join("notes.md"), "not a version").unwrap();
+
+        let versions = list_versions_in(dir.path(), "a").unwrap();
+        let stamps: Vec<(&str, &str, u64)> = versions.iter().map(|v| (v.timestamp.as_str(), v.saved_at.as_str(), v.size)).collect();
+        assert_eq!(stamps, [("20240102T000000000Z", "2024-01-02T00:00:00Z", 5), ("20240101T000000000Z", "2024-01-01T00:00:00Z", 3)]);
+    }
+
+    #[test]
+    fn listing_versions_needs_an_indexed_id() {
+        let dir = notes_folder(&[("a", "A", "v1")]);
+        // `..` would list `notes/` itself, and `""` the whole history folder.
+        fs::write(dir.path().join("20240101T000000000Z.md"), "not a version").unwrap();
+        for id in ["..", "", "missing"] {
+            assert!(matches!(list_versions_in(dir.path(), id), Err(AppError::NotFound(_))), "{id:?}");
+        }
+        assert!(list_versions_in(dir.path(), "a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn snapshots_keep_at_most_the_limit() {
+        let dir = notes_folder(&[("a", "A", "v1")]);
+        for content in ["v2", "v3", "v4"] {
+            snapshot_in(dir.path(), "a", 2).unwrap();
+            fs::write(note_file(dir.path(), "a"), content).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let kept: Vec<String> = version_stems(&history_dir(dir.path(), "a"))
+            .iter()
+            .map(|stem| fs::read_to_string(history_dir(dir.path(), "a").join(format!("{stem}.md"))).unwrap())
+            .collect();
+        assert_eq!(kept, ["v2", "v3"]);
+        snapshot_in(dir.path(), "b", 2).unwrap();
+        assert!(!history_dir(dir.path(), "b").exists());
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Emitter};
+
+use crate::{error::AppError, notes_dir, NOTE_EXTENSIONS};
+
+/// Quiet period before a burst of file events for one note is reported.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// Events this soon after the app wrote a note itself are treated as our own.
+const OWN_WRITE_GRACE: Duration = Duration::from_secs(2);
+
+static WATCHER: Lazy<Mutex<Option<RecommendedWatcher>>> = Lazy::new(|| Mutex::new(None));
+static OWN_WRITES: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records that the app itself is about to change a note file so the watcher ignores it.
+pub(crate) fn mark_own_write(note_id: &str) {
+    let mut writes = OWN_WRITES.lock().unwrap();
+    writes.retain(|_, at| at.elapsed() < OWN_WRITE_GRACE);
+    writes.insert(note_id.to_string(), Instant::now());
+}
+
+fn is_own_write(note_id: &str) -> bool {
+    OWN_WRITES
+        .lock()
+        .unwrap()
+        .get(note_id)
+        .is_some_and(|at| at.elapsed() < OWN_WRITE_GRACE)
+}
+
+fn note_id_for(path: &Path) -> Option<String> {
+    // Not `is_note_file`: a deleted note's path no longer exists.
+    if !path.extension().and_then(|s| s.to_str()).is_some_and(|ext| NOTE_EXTENSIONS.contains(&ext)) {
+        return None;
+    }
+    path.file_stem().and_then(|s| s.to_str()).map(str::to_string)
+}
+
+/// Points a running watcher at the current notes folder after it moved.
+pub(crate) fn rewatch(app: &AppHandle) -> Result<(), AppError> {
+    // Dropping the old watcher also ends its debounce thread.
+    let was_running = WATCHER.lock().unwrap().take().is_some();
+    if was_running {
+        start_watching_notes(app.clone())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn start_watching_notes(app: AppHandle) -> Result<String, AppError> {
+    let mut slot = WATCHER.lock().unwrap();
+    if slot.is_some() {
+        return Ok("already".into());
+    }
+
+    let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&notes_dir(&app)?, RecursiveMode::NonRecursive)?;
+    *slot = Some(watcher);
+
+    std::thread::spawn(move || {
+        let mut pending: HashMap<String, Instant> = HashMap::new();
+        loop {
+            match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Access(_)) {
+                        continue;
+                    }
+                    for id in event.paths.iter().filter_map(|p| note_id_for(p)) {
+                        if !is_own_write(&id) {
+                            pending.insert(id, Instant::now());
+                        }
+                    }
+                }
+                Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // The watcher was dropped, so nothing more will arrive.
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            pending.retain(|id, last| {
+                if last.elapsed() < DEBOUNCE {
+                    return true;
+                }
+                let _ = app.emit("note://external_change", &serde_json::json!({ "id": id }));
+                false
+            });
+        }
+    });
+
+    Ok("started".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_id_for_only_names_note_files() {
+        assert_eq!(note_id_for(Path::new("/notes/abc.md")).as_deref(), Some("abc"));
+        assert_eq!(note_id_for(Path::new("/notes/abc.txt")).as_deref(), Some("abc"));
+        assert_eq!(note_id_for(Path::new("/notes/index.json")), None);
+        assert_eq!(note_id_for(Path::new("/notes/abc.md.tmp")), None);
+        assert_eq!(note_id_for(Path::new("/notes/attachments")), None);
+    }
+
+    #[test]
+    fn own_writes_are_recognised() {
+        let id = uuid::Uuid::new_v4().to_string();
+        assert!(!is_own_write(&id));
+        mark_own_write(&id);
+        assert!(is_own_write(&id));
+    }
+}
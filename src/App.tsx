@@ -59,7 +59,7 @@ export default function App() {
   async function refreshNotes() {
     setIsNotesLoading(true);
     try {
-      const storedNotes = await listNotes();
+      const { notes: storedNotes } = await listNotes();
       setNotes(sortByUpdated(storedNotes));
     } catch (error) {
       console.error(error);
@@ -310,7 +310,11 @@ export default function App() {
       unsubs.push(
         await listen("share://recv_status", ({ payload }) => {
           const p = payload as any;
-          if (p?.phase) {
+          if (p?.phase === "receiving" && typeof p.received === "number" && typeof p.total === "number") {
+            const pct = p.total ? Math.min(100, Math.floor((p.received * 100) / p.total)) : 0;
+            setNetStatus(`Receiving… ${pct}%`);
+            setNetProgress(pct);
+          } else if (p?.phase) {
             setNetStatus(String(p.phase));
             if (p.phase === "listening") setIsReceiving(true);
             if (p.phase === "stopped") setIsReceiving(false);
@@ -320,6 +324,7 @@ export default function App() {
       unsubs.push(
         await listen("share://recv_done", async ({ payload }) => {
           const p = payload as any;
+          setNetProgress(undefined);
           if (p?.ok) {
             toast.success(p?.message || "Received notes");
             await refreshNotes();
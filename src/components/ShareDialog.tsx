@@ -2,7 +2,7 @@ import { Dialog, DialogContent, DialogHeader, DialogTitle } from "@/components/u
 import { Button } from "@/components/ui/button";
 import { useEffect } from "react";
 
-export type PeerInfo = { name: string; ip: string; port: number; id: string };
+export type PeerInfo = { name: string; ip: string; port: number; id: string; noteCount?: number };
 
 type ShareDialogProps = {
   open: boolean;
@@ -41,7 +41,10 @@ export default function ShareDialog({ open, peers, busy, title = "Select a recei
                     {(p.name || p.ip).slice(0, 2).toUpperCase()}
                   </div>
                   <div className="min-w-0">
-                    <div className="font-medium truncate">{p.name || p.ip}</div>
+                    <div className="font-medium truncate">
+                      {p.name || p.ip}
+                      {p.noteCount !== undefined && ` — ${p.noteCount} notes`}
+                    </div>
                     <div className="text-muted-foreground text-xs truncate">{p.ip}:{p.port}</div>
                   </div>
                 </button>
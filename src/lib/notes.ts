@@ -5,6 +5,9 @@ export type NoteSummary = {
   title: string;
   updatedAt: string;
   preview: string;
+  color?: string;
+  tags?: string[];
+  archived?: boolean;
 };
 
 export type NoteDocument = {
@@ -12,10 +15,36 @@ export type NoteDocument = {
   title: string;
   content: string;
   updatedAt: string;
+  createdAt?: string;
+  color?: string;
+  tags?: string[];
+  archived?: boolean;
 };
 
-export async function listNotes(): Promise<NoteSummary[]> {
-  return invoke<NoteSummary[]>("list_notes");
+/** Shape of errors rejected by backend commands. */
+export type AppError = {
+  kind:
+    | "notFound"
+    | "io"
+    | "serde"
+    | "network"
+    | "conflict"
+    | "invalidId"
+    | "invalidInput"
+    | "encryption"
+    | "missingContent";
+  message: string;
+};
+
+export type NotesPage = {
+  notes: NoteSummary[];
+  total: number;
+};
+
+export type NoteSort = "updated" | "created" | "title";
+
+export async function listNotes(offset?: number, limit?: number, sort?: NoteSort): Promise<NotesPage> {
+  return invoke<NotesPage>("list_notes", { offset, limit, sort });
 }
 
 export async function loadNote(id: string): Promise<NoteDocument> {
@@ -26,6 +55,10 @@ export async function saveNote(note: NoteDocument): Promise<NoteSummary> {
   return invoke<NoteSummary>("save_note", { note });
 }
 
+export async function saveNotes(notes: NoteDocument[]): Promise<NoteSummary[]> {
+  return invoke<NoteSummary[]>("save_notes", { notes });
+}
+
 export async function deleteNote(id: string): Promise<void> {
   return invoke<void>("delete_note", { id });
 }